@@ -0,0 +1,138 @@
+use std::os::unix::io::RawFd;
+
+use crate::config::Color;
+use crate::key::{KeyMap, ModMask};
+use crate::rect::Rect;
+use crate::x::{Cursor, SizeHints, Window, WindowChanges, WindowId, XEvent};
+
+/// Requests the window management logic makes of the display server, kept
+/// behind a trait so desktops and layouts can run against a fake in tests
+pub trait Backend {
+    /// Take over managing the screen and grab keys on the root window
+    fn setup(&self, keys: &KeyMap);
+
+    /// Top level windows that a WM could manage, along with whether each is
+    /// currently mapped. Override redirect windows are left out
+    fn get_existing_windows(&self) -> Vec<(Window, bool)>;
+
+    /// Area of each monitor, left to right, from RandR or else Xinerama.
+    /// Without either the root window is the only monitor. Mirrored outputs
+    /// count once
+    fn get_monitors(&self) -> Vec<Rect>;
+
+    /// Give up being the WM: stop redirecting requests, drop key grabs and
+    /// hand focus back to the pointer
+    fn release(&self);
+
+    /// Store the state handed to the next instance of worm on restart
+    fn set_saved_state(&self, state: &str);
+
+    /// Read and remove the state left by a previous instance of worm
+    fn take_saved_state(&self) -> Option<String>;
+
+    fn root_window(&self) -> Window;
+
+    /// Block until an X event arrives or wakeup becomes readable, in which case
+    /// None is returned
+    fn wait_for_event(&self, wakeup: RawFd) -> Option<XEvent>;
+
+    fn register_window(&self, window: &Window);
+
+    /// Grab the buttons that move and resize a window while modifier is held,
+    /// replacing any grabbed before. Clicks to focus freeze the pointer until
+    /// replay_pointer passes them on to the window
+    fn grab_buttons(&self, window: &Window, modifier: ModMask, click_to_focus: bool);
+
+    /// Take over the pointer until ungrab_pointer, showing cursor. Returns
+    /// false if something else holds it
+    fn grab_pointer(&self, cursor: Cursor) -> bool;
+
+    fn ungrab_pointer(&self);
+
+    /// Pass a click frozen by a click to focus grab on to the window under
+    /// the pointer
+    fn replay_pointer(&self);
+
+    fn flush(&self);
+
+    fn map_window(&self, window: &Window);
+
+    /// Unmaps made here are not reported back as the client withdrawing
+    fn unmap_window(&self, window: &Window);
+
+    fn configure_window(&self, window: &Window, window_changes: &WindowChanges);
+
+    /// Create an unmapped frame with a title bar of title_height above the
+    /// window and reparent the window into it
+    fn create_frame(&self, window: &Window, title_height: u32) -> Window;
+
+    /// Put the window back on the root where its frame was and destroy the
+    /// frame
+    fn destroy_frame(&self, frame: &Window, window: &Window);
+
+    /// Tell the client how much its frame adds around it
+    fn set_frame_extents(&self, window: &Window, border_width: u32, title_height: u32);
+
+    fn draw_title(
+        &self,
+        frame: &Window,
+        title: &str,
+        height: u32,
+        foreground: Color,
+        background: Color,
+    );
+
+    /// Publish every managed window, in the order they are managed and
+    /// from the bottom of the stack to the top
+    fn set_client_list(&self, windows: &[WindowId], stacking: &[WindowId]);
+
+    /// Publish the number of desktops and their names
+    fn set_desktop_names(&self, names: &[String]);
+
+    fn set_current_desktop(&self, desktop: usize);
+
+    fn set_window_desktop(&self, window: &Window, desktop: usize);
+
+    fn set_border_width(&self, window: &Window, width: u32);
+
+    /// Stack the window above its siblings
+    fn raise_window(&self, window: &Window);
+
+    /// Record in _NET_WM_STATE whether the window is fullscreen
+    fn set_fullscreen_state(&self, window: &Window, fullscreen: bool);
+
+    fn set_border_color(&self, window: &Window, color: Color);
+
+    fn is_viewable(&self, window: &Window) -> bool;
+
+    fn focus_window(&self, window: Window);
+
+    /// Ask the window to close, killing its client if it does not support
+    /// WM_DELETE_WINDOW
+    fn delete_window(&self, window: &Window);
+
+    fn grab_keys(&self, window: &Window, keys: &KeyMap);
+
+    /// Release every key grab on the window
+    fn ungrab_keys(&self, window: &Window);
+
+    fn track_window_events(&self, window: &Window);
+
+    fn stop_window_events(&self, window: &Window);
+
+    /// Returns the (instance, class) pair from WM_CLASS
+    fn get_wm_class(&self, window: &Window) -> (String, String);
+
+    /// Returns _NET_WM_NAME, falling back to WM_NAME
+    fn get_window_title(&self, window: &Window) -> String;
+
+    fn get_size_hints(&self, window: &Window) -> SizeHints;
+
+    /// Returns whether WM_HINTS has the urgency flag set
+    fn is_urgent(&self, window: &Window) -> bool;
+
+    /// Returns whether _NET_WM_STATE has the fullscreen state set
+    fn is_fullscreen(&self, window: &Window) -> bool;
+
+    fn get_transient_for(&self, window: &Window) -> Option<WindowId>;
+}
@@ -1,31 +1,58 @@
-use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process;
 
-use worm::command::*;
-use worm::key::*;
-use worm::layout::*;
+use worm::config::Config;
 use worm::*;
 
-use x11::keysym::*;
+fn usage() -> ! {
+    eprintln!("Usage: worm [--config <path>] [--list-bindings]");
+    process::exit(1);
+}
 
 fn main() {
     println!("Worm - X Window Manager");
 
-    let binds = bindings!(
-        (&[Modifier::Mod1], XK_t, Command::ChangeLayout(Layout::Tile)),
-        (&[Modifier::Mod1], XK_s, Command::ChangeLayout(Layout::Float)),
-        (&[Modifier::Mod1], XK_m, Command::ChangeLayout(Layout::Monocle)),
-        (&[Modifier::Mod1], XK_h, Command::FocusDirection(Direction::Left)),
-        (&[Modifier::Mod1], XK_j, Command::FocusDirection(Direction::Down)),
-        (&[Modifier::Mod1], XK_k, Command::FocusDirection(Direction::Up)),
-        (&[Modifier::Mod1], XK_l, Command::FocusDirection(Direction::Right)),
-        (&[Modifier::Mod1, Modifier::Shift], XK_k, Command::MoveDirection(Direction::Up)),
-        (&[Modifier::Mod1, Modifier::Shift], XK_j, Command::MoveDirection(Direction::Down)),
-        (&[Modifier::Mod1, Modifier::Shift], XK_h, Command::MoveDirection(Direction::Left)),
-        (&[Modifier::Mod1, Modifier::Shift], XK_l, Command::MoveDirection(Direction::Right)),
-        (&[Modifier::Mod1], XK_w, Command::KillFocused)
-    );
-
-    let mut wm = Worm::new(binds);
+    let mut config_path: Option<PathBuf> = None;
+    let mut list_bindings = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" | "-c" => match args.next() {
+                Some(path) => config_path = Some(PathBuf::from(path)),
+                None => usage(),
+            },
+            "--list-bindings" => list_bindings = true,
+            _ => usage(),
+        }
+    }
+
+    // A missing config file is only an error if it was asked for explicitly
+    let explicit = config_path.is_some();
+    let config = match config_path.or_else(Config::default_path) {
+        Some(path) if explicit || path.exists() => match Config::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("worm: {}", e);
+                if list_bindings {
+                    process::exit(1);
+                }
+                eprintln!("worm: falling back to the default configuration");
+                Config::default()
+            }
+        },
+        _ => Config::default(),
+    };
+
+    // Validates the config without starting the WM
+    if list_bindings {
+        for (key, command) in config.binds.describe() {
+            println!("{:<30} {}", key, command);
+        }
+        return;
+    }
+
+    let mut wm = Worm::new(config);
 
     wm.run();
 }
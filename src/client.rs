@@ -0,0 +1,173 @@
+use crate::backend::Backend;
+use crate::config::Color;
+use crate::rect::Rect;
+use crate::x;
+
+/// A managed window along with the state the WM keeps about it. The window
+/// lives inside a frame drawn by the WM, so its own position is relative to
+/// the frame
+#[derive(Clone, Debug)]
+pub struct Client {
+    pub window: x::Window,
+    pub frame: x::Window,
+    /// Height of the title bar the window sits below in its frame
+    pub title_height: u32,
+    pub instance: String,
+    pub class: String,
+    pub title: String,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub urgent: bool,
+    pub sticky: bool,
+    pub size_hints: x::SizeHints,
+    pub transient_for: Option<x::WindowId>,
+    /// Last geometry given to the window by a tiling layout
+    pub tiled_geometry: Option<Rect>,
+    /// Last geometry the window had while floating
+    pub floating_geometry: Option<Rect>,
+    /// Border width and color last set by the WM
+    pub border_width: u32,
+    pub border_color: Option<Color>,
+    /// Whether the WM has mapped the frame
+    pub mapped: bool,
+    /// Whether the frame was raised above the tiled windows since anything
+    /// could have covered it
+    pub raised: bool,
+}
+
+impl Client {
+    /// Start managing window, reparenting it into a new frame
+    pub fn new(connection: &dyn Backend, window: x::Window, title_height: u32) -> Client {
+        let (instance, class) = connection.get_wm_class(&window);
+        let transient_for = connection.get_transient_for(&window);
+        let size_hints = connection.get_size_hints(&window);
+        let frame = connection.create_frame(&window, title_height);
+        let inside = Rect::new(0, title_height as i32, window.width, window.height);
+        let mut window = window;
+        window.set_rect(inside);
+        Client {
+            window,
+            frame,
+            title_height,
+            instance,
+            class,
+            title: connection.get_window_title(&window),
+            // Dialogs float over the window they belong to, and windows
+            // that cannot be resized are not stretched into a tile
+            floating: transient_for.is_some() || size_hints.is_fixed(),
+            fullscreen: connection.is_fullscreen(&window),
+            urgent: connection.is_urgent(&window),
+            sticky: false,
+            size_hints,
+            transient_for,
+            tiled_geometry: None,
+            floating_geometry: None,
+            border_width: 0,
+            border_color: None,
+            mapped: false,
+            raised: false,
+        }
+    }
+
+    /// Geometry of the frame, which is what layouts place
+    pub fn geometry(&self) -> Rect {
+        self.frame.rect()
+    }
+
+    pub fn map(&mut self, connection: &dyn Backend) {
+        if !self.mapped {
+            connection.map_window(&self.frame);
+            self.mapped = true;
+        }
+    }
+
+    pub fn unmap(&mut self, connection: &dyn Backend) {
+        if self.mapped {
+            connection.unmap_window(&self.frame);
+            self.mapped = false;
+        }
+    }
+
+    /// Stack the frame above the tiled windows unless it still is
+    pub fn raise(&mut self, connection: &dyn Backend) {
+        if !self.raised {
+            connection.raise_window(&self.frame);
+            self.raised = true;
+        }
+    }
+
+    /// Move and resize the frame, fitting the window below a title bar of
+    /// title_height
+    pub fn configure(&mut self, connection: &dyn Backend, geometry: Rect, title_height: u32) {
+        let inside = Rect::new(
+            0,
+            title_height as i32,
+            geometry.width,
+            geometry.height.saturating_sub(title_height),
+        )
+        .at_least_min_size();
+        connection.configure_window(&self.frame, &x::WindowChanges::from(geometry));
+        connection.configure_window(&self.window, &x::WindowChanges::from(inside));
+        self.frame.set_rect(geometry);
+        self.window.set_rect(inside);
+        self.title_height = title_height;
+    }
+
+    /// The frame geometry closest to geometry that leaves the window a size
+    /// its size hints allow
+    pub fn constrain(&self, geometry: Rect) -> Rect {
+        let height = geometry.height.saturating_sub(self.title_height);
+        let (width, height) = self.size_hints.constrain(geometry.width, height);
+        Rect {
+            width,
+            height: height.saturating_add(self.title_height),
+            ..geometry
+        }
+    }
+
+    /// Fill the title bar with the border color and write the title over it
+    pub fn draw_title(&self, connection: &dyn Backend, foreground: Color) {
+        if let Some(background) = self.border_color.filter(|_| self.title_height > 0) {
+            connection.draw_title(
+                &self.frame,
+                &self.title,
+                self.title_height,
+                foreground,
+                background,
+            );
+        }
+    }
+
+    /// Re-read a property after the client changed it
+    pub fn update_property(&mut self, connection: &dyn Backend, property: x::Property) {
+        match property {
+            x::Property::Name => self.title = connection.get_window_title(&self.window),
+            x::Property::Class => {
+                let (instance, class) = connection.get_wm_class(&self.window);
+                self.instance = instance;
+                self.class = class;
+            }
+            x::Property::NormalHints => self.size_hints = connection.get_size_hints(&self.window),
+            x::Property::Hints => self.urgent = connection.is_urgent(&self.window),
+            x::Property::TransientFor => {
+                self.transient_for = connection.get_transient_for(&self.window)
+            }
+            x::Property::Other => {}
+        }
+    }
+
+    /// Record the geometry the X server reported for the window within its
+    /// frame
+    pub fn update_geometry(&mut self, window: &x::Window, floating: bool) {
+        self.window.set_rect(window.rect());
+        // Fullscreen geometry is not where the window goes back to
+        if floating && !self.fullscreen {
+            self.floating_geometry = Some(self.geometry());
+        }
+    }
+
+    /// Record the geometry a tiling layout configured the window with
+    pub fn set_tiled_geometry(&mut self, geometry: Rect) {
+        self.tiled_geometry = Some(geometry);
+    }
+}
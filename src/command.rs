@@ -1,5 +1,8 @@
+use std::fmt;
+use std::str::FromStr;
+
 use crate::layout::Layout;
-use crate::x::Window;
+use crate::spawn::Spawn;
 use crate::Worm;
 
 #[derive(Copy, Clone)]
@@ -10,13 +13,149 @@ pub enum Direction {
     Down,
 }
 
-#[derive(Copy, Clone)]
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Direction, String> {
+        match s {
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            _ => Err(format!("unknown direction \"{}\"", s)),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Direction::Left => "left",
+            Direction::Right => "right",
+            Direction::Up => "up",
+            Direction::Down => "down",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone)]
 pub enum Command {
     ChangeLayout(Layout),
     FocusDirection(Direction),
     FocusDesktop(usize),
     MoveDirection(Direction),
+    ChangeMasterCount(isize),
+    ChangeMasterFactor(f32),
+    ChangeInnerGap(i32),
+    ChangeOuterGap(i32),
+    ToggleGaps,
+    FocusMonitor(isize),
+    MoveToMonitor(isize),
+    MoveToDesktop(usize),
+    MoveToDesktopAndFollow(usize),
     KillFocused,
+    ToggleFullscreen,
+    ToggleFloating,
+    ReloadConfig,
+    Spawn(Spawn),
+    Quit,
+    Restart,
+}
+
+/// Parses commands written as a name followed by its argument, e.g.
+/// "focus-desktop 2" or "change-layout monocle". Spawn commands take the
+/// program and its arguments, e.g. "spawn urxvt -e htop"
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Command, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let (name, args) = words
+            .split_first()
+            .ok_or_else(|| String::from("empty command"))?;
+
+        // Spawned programs take the rest of the line as their arguments
+        match *name {
+            "spawn" => return Ok(Command::Spawn(Spawn::from_words(args, false)?)),
+            "spawn-shell" => return Ok(Command::Spawn(Spawn::from_words(args, true)?)),
+            _ => {}
+        }
+
+        if args.len() > 1 {
+            return Err(format!("too many arguments to \"{}\"", name));
+        }
+        let arg = |what: &str| {
+            args.first()
+                .copied()
+                .ok_or_else(|| format!("\"{}\" needs a {}", name, what))
+        };
+        let index = |what: &str| -> Result<usize, String> { parse_number(arg(what)?, what) };
+        let delta = || -> Result<&str, String> { arg("number") };
+
+        let command = match *name {
+            "change-layout" => Command::ChangeLayout(arg("layout")?.parse()?),
+            "focus-direction" => Command::FocusDirection(arg("direction")?.parse()?),
+            "focus-desktop" => Command::FocusDesktop(index("desktop index")?),
+            "move-direction" => Command::MoveDirection(arg("direction")?.parse()?),
+            "change-master-count" => Command::ChangeMasterCount(parse_number(delta()?, "number")?),
+            "change-master-factor" => {
+                Command::ChangeMasterFactor(parse_number(delta()?, "number")?)
+            }
+            "change-inner-gap" => Command::ChangeInnerGap(parse_number(delta()?, "number")?),
+            "change-outer-gap" => Command::ChangeOuterGap(parse_number(delta()?, "number")?),
+            "toggle-gaps" => Command::ToggleGaps,
+            "focus-monitor" => Command::FocusMonitor(parse_number(delta()?, "number")?),
+            "move-to-monitor" => Command::MoveToMonitor(parse_number(delta()?, "number")?),
+            "move-to-desktop" => Command::MoveToDesktop(index("desktop index")?),
+            "move-to-desktop-and-follow" => {
+                Command::MoveToDesktopAndFollow(index("desktop index")?)
+            }
+            "kill-focused" => Command::KillFocused,
+            "toggle-fullscreen" => Command::ToggleFullscreen,
+            "toggle-floating" => Command::ToggleFloating,
+            "reload-config" => Command::ReloadConfig,
+            "quit" => Command::Quit,
+            "restart" => Command::Restart,
+            _ => return Err(format!("unknown command \"{}\"", name)),
+        };
+        Ok(command)
+    }
+}
+
+/// Parse a numeric argument, naming what it should have been if invalid
+fn parse_number<T: FromStr>(arg: &str, what: &str) -> Result<T, String> {
+    arg.parse()
+        .map_err(|_| format!("\"{}\" is not a valid {}", arg, what))
+}
+
+/// Formats commands the same way they are parsed
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::ChangeLayout(l) => write!(f, "change-layout {}", l),
+            Command::FocusDirection(d) => write!(f, "focus-direction {}", d),
+            Command::FocusDesktop(i) => write!(f, "focus-desktop {}", i),
+            Command::MoveDirection(d) => write!(f, "move-direction {}", d),
+            Command::ChangeMasterCount(n) => write!(f, "change-master-count {}", n),
+            Command::ChangeMasterFactor(n) => write!(f, "change-master-factor {}", n),
+            Command::ChangeInnerGap(n) => write!(f, "change-inner-gap {}", n),
+            Command::ChangeOuterGap(n) => write!(f, "change-outer-gap {}", n),
+            Command::ToggleGaps => write!(f, "toggle-gaps"),
+            Command::FocusMonitor(n) => write!(f, "focus-monitor {}", n),
+            Command::MoveToMonitor(n) => write!(f, "move-to-monitor {}", n),
+            Command::MoveToDesktop(i) => write!(f, "move-to-desktop {}", i),
+            Command::MoveToDesktopAndFollow(i) => write!(f, "move-to-desktop-and-follow {}", i),
+            Command::KillFocused => write!(f, "kill-focused"),
+            Command::ToggleFullscreen => write!(f, "toggle-fullscreen"),
+            Command::ToggleFloating => write!(f, "toggle-floating"),
+            Command::ReloadConfig => write!(f, "reload-config"),
+            Command::Spawn(s) if s.shell => write!(f, "spawn-shell {}", s),
+            Command::Spawn(s) => write!(f, "spawn {}", s),
+            Command::Quit => write!(f, "quit"),
+            Command::Restart => write!(f, "restart"),
+        }
+    }
 }
 
 impl Command {
@@ -24,9 +163,24 @@ impl Command {
         match self {
             Command::ChangeLayout(l) => Command::change_layout(wm, l),
             Command::FocusDirection(d) => Command::focus_direction(wm, d),
+            Command::FocusDesktop(i) => Command::focus_desktop(wm, *i),
             Command::MoveDirection(d) => Command::move_direction(wm, d),
+            Command::ChangeMasterCount(n) => Command::change_master_count(wm, *n),
+            Command::ChangeMasterFactor(n) => Command::change_master_factor(wm, *n),
+            Command::ChangeInnerGap(n) => Command::change_inner_gap(wm, *n),
+            Command::ChangeOuterGap(n) => Command::change_outer_gap(wm, *n),
+            Command::ToggleGaps => Command::toggle_gaps(wm),
+            Command::FocusMonitor(n) => Command::focus_monitor(wm, *n),
+            Command::MoveToMonitor(n) => Command::move_to_monitor(wm, *n),
+            Command::MoveToDesktop(i) => Command::move_to_desktop(wm, *i, false),
+            Command::MoveToDesktopAndFollow(i) => Command::move_to_desktop(wm, *i, true),
             Command::KillFocused => Command::kill_focused(wm),
-            _ => return,
+            Command::ToggleFullscreen => Command::toggle_fullscreen(wm),
+            Command::ToggleFloating => Command::toggle_floating(wm),
+            Command::ReloadConfig => wm.reload_config(),
+            Command::Spawn(s) => s.spawn(),
+            Command::Quit => wm.quit(),
+            Command::Restart => wm.restart(),
         };
     }
 
@@ -36,18 +190,57 @@ impl Command {
 
     fn focus_direction(wm: &mut Worm, direction: &Direction) {
         wm.desktops.focus_window(direction);
-        println!(
-            "FOCUSED: {:?}, FOCUSED_LAST: {:?}",
-            wm.desktops.desktops[wm.desktops.focused_desktop].focused_window,
-            wm.desktops.desktops[wm.desktops.focused_desktop].focused_last
-        );
+    }
+
+    fn focus_desktop(wm: &mut Worm, index: usize) {
+        wm.desktops.focus_desktop(index);
     }
 
     fn move_direction(wm: &mut Worm, direction: &Direction) {
         wm.desktops.move_window_tile(direction);
     }
 
+    fn change_master_count(wm: &mut Worm, delta: isize) {
+        wm.desktops.change_master_count(delta);
+    }
+
+    fn change_master_factor(wm: &mut Worm, delta: f32) {
+        wm.desktops.change_master_factor(delta);
+    }
+
+    fn change_inner_gap(wm: &mut Worm, delta: i32) {
+        wm.desktops.change_inner_gap(delta);
+    }
+
+    fn change_outer_gap(wm: &mut Worm, delta: i32) {
+        wm.desktops.change_outer_gap(delta);
+    }
+
+    fn toggle_gaps(wm: &mut Worm) {
+        wm.desktops.toggle_gaps();
+    }
+
+    fn focus_monitor(wm: &mut Worm, delta: isize) {
+        wm.desktops.focus_monitor(delta);
+    }
+
+    fn move_to_monitor(wm: &mut Worm, delta: isize) {
+        wm.desktops.move_focused_to_monitor(delta);
+    }
+
+    fn move_to_desktop(wm: &mut Worm, index: usize, follow: bool) {
+        wm.desktops.move_focused_to_desktop(index, follow);
+    }
+
     fn kill_focused(wm: &mut Worm) {
         wm.desktops.delete_focused_window();
     }
+
+    fn toggle_fullscreen(wm: &mut Worm) {
+        wm.desktops.toggle_fullscreen();
+    }
+
+    fn toggle_floating(wm: &mut Worm) {
+        wm.desktops.toggle_floating();
+    }
 }
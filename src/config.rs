@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::bindings;
+use crate::client::Client;
+use crate::command::*;
+use crate::key::*;
+use crate::layout::*;
+use crate::spawn::Spawn;
+
+use x11::keysym::*;
+
+/// Colors are stored as the pixel values X expects, 0xRRGGBB
+pub type Color = u32;
+
+pub struct Config {
+    /// File the config was loaded from, None for the defaults
+    pub path: Option<PathBuf>,
+    pub binds: KeyMap,
+    pub desktops: Vec<String>,
+    pub layout: Layout,
+    pub tile: TileSettings,
+    /// Gaps of desktops that override the ones in tile, by desktop name
+    pub desktop_gaps: HashMap<String, Gaps>,
+    pub border: BorderConfig,
+    pub rules: Vec<Rule>,
+    /// Modifier held to move windows with button 1 and resize with button 3
+    pub mouse_modifier: ModMask,
+    pub focus_model: FocusModel,
+}
+
+/// How the pointer moves focus between windows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FocusModel {
+    /// Focus follows the pointer into windows
+    Sloppy,
+    /// Clicking a window focuses it, the click still reaches the window
+    Click,
+}
+
+impl FromStr for FocusModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FocusModel, String> {
+        match s {
+            "sloppy" => Ok(FocusModel::Sloppy),
+            "click" => Ok(FocusModel::Click),
+            _ => Err(format!("unknown focus model \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BorderConfig {
+    pub width: u32,
+    pub focused: Color,
+    pub unfocused: Color,
+    pub urgent: Color,
+    /// Height of the title bar above each window, 0 hides it
+    pub title_height: u32,
+    /// Text color of titles, drawn over the border color
+    pub title_color: Color,
+}
+
+/// Settings applied to newly managed windows matching every given field
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rule {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub title: Option<String>,
+    pub desktop: Option<usize>,
+    pub floating: Option<bool>,
+}
+
+impl Rule {
+    pub fn matches(&self, client: &Client) -> bool {
+        let field = |want: &Option<String>, have: &str| want.iter().all(|w| w == have);
+        field(&self.class, &client.class)
+            && field(&self.instance, &client.instance)
+            && field(&self.title, &client.title)
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    /// Line and column of the offending value, both starting at 1
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path.display(),
+                line,
+                column,
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl Default for BorderConfig {
+    fn default() -> BorderConfig {
+        BorderConfig {
+            width: 1,
+            focused: 0x005577,
+            unfocused: 0x444444,
+            urgent: 0xff0000,
+            title_height: 18,
+            title_color: 0xffffff,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let binds = bindings!(
+            (&[Modifier::Mod1], XK_t, Command::ChangeLayout(Layout::Tile)),
+            (&[Modifier::Mod1], XK_s, Command::ChangeLayout(Layout::Float)),
+            (&[Modifier::Mod1], XK_m, Command::ChangeLayout(Layout::Monocle)),
+            (&[Modifier::Mod1], XK_h, Command::FocusDirection(Direction::Left)),
+            (&[Modifier::Mod1], XK_j, Command::FocusDirection(Direction::Down)),
+            (&[Modifier::Mod1], XK_k, Command::FocusDirection(Direction::Up)),
+            (&[Modifier::Mod1], XK_l, Command::FocusDirection(Direction::Right)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_k, Command::MoveDirection(Direction::Up)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_j, Command::MoveDirection(Direction::Down)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_h, Command::MoveDirection(Direction::Left)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_l, Command::MoveDirection(Direction::Right)),
+            (&[Modifier::Mod1], XK_i, Command::ChangeMasterCount(1)),
+            (&[Modifier::Mod1], XK_d, Command::ChangeMasterCount(-1)),
+            (&[Modifier::Mod1, Modifier::Control], XK_l, Command::ChangeMasterFactor(0.05)),
+            (&[Modifier::Mod1, Modifier::Control], XK_h, Command::ChangeMasterFactor(-0.05)),
+            (&[Modifier::Mod1], XK_equal, Command::ChangeInnerGap(2)),
+            (&[Modifier::Mod1], XK_minus, Command::ChangeInnerGap(-2)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_equal, Command::ChangeOuterGap(2)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_minus, Command::ChangeOuterGap(-2)),
+            (&[Modifier::Mod1], XK_g, Command::ToggleGaps),
+            (&[Modifier::Mod1], XK_period, Command::FocusMonitor(1)),
+            (&[Modifier::Mod1], XK_comma, Command::FocusMonitor(-1)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_period, Command::MoveToMonitor(1)),
+            (&[Modifier::Mod1, Modifier::Shift], XK_comma, Command::MoveToMonitor(-1)),
+            (&[Modifier::Mod1], XK_w, Command::KillFocused),
+            (&[Modifier::Mod1], XK_f, Command::ToggleFullscreen),
+            (&[Modifier::Mod1, Modifier::Shift], XK_space, Command::ToggleFloating),
+            (&[Modifier::Mod1, Modifier::Shift], XK_r, Command::ReloadConfig),
+            (&[Modifier::Mod1, Modifier::Control], XK_r, Command::Restart),
+            (&[Modifier::Mod1, Modifier::Shift], XK_q, Command::Quit),
+            (&[Modifier::Mod1, Modifier::Shift], XK_Return, Command::Spawn(Spawn {
+                argv: vec![String::from("xterm")],
+                shell: false,
+                env: Vec::new(),
+            }))
+        );
+        let mut config = Config {
+            path: None,
+            binds,
+            desktops: (0..9).map(|i| i.to_string()).collect(),
+            layout: Layout::Tile,
+            tile: TileSettings::default(),
+            desktop_gaps: HashMap::new(),
+            border: BorderConfig::default(),
+            rules: Vec::new(),
+            mouse_modifier: Modifier::Mod1.get_mod_mask(),
+            focus_model: FocusModel::Click,
+        };
+
+        let desktop_keys = [XK_1, XK_2, XK_3, XK_4, XK_5, XK_6, XK_7, XK_8, XK_9];
+        for (i, key) in desktop_keys.iter().enumerate() {
+            let binds = &mut config.binds.key_map;
+            binds.insert(Key::new(&[Modifier::Mod1], *key), Command::FocusDesktop(i));
+            binds.insert(
+                Key::new(&[Modifier::Mod1, Modifier::Shift], *key),
+                Command::MoveToDesktop(i),
+            );
+            binds.insert(
+                Key::new(&[Modifier::Mod1, Modifier::Control], *key),
+                Command::MoveToDesktopAndFollow(i),
+            );
+        }
+
+        config
+    }
+}
+
+// On disk representation, validated into Config. Values that can be wrong in
+// ways TOML itself does not catch are Spanned so errors can point at them
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    desktops: Option<Spanned<Vec<String>>>,
+    layout: Option<Spanned<String>>,
+    mouse_modifier: Option<Spanned<String>>,
+    focus: Option<Spanned<String>>,
+    #[serde(default)]
+    tile: RawTile,
+    #[serde(default)]
+    gaps: RawGaps,
+    #[serde(default)]
+    border: RawBorder,
+    bind: Option<Vec<RawBind>>,
+    #[serde(default)]
+    rule: Vec<RawRule>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTile {
+    num_master: Option<usize>,
+    master_fact: Option<Spanned<f32>>,
+    size_hints: Option<bool>,
+}
+
+// Desktops override gaps in tables of the same shape, nested under their name
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawGaps {
+    inner: Option<u32>,
+    outer: Option<u32>,
+    outer_top: Option<u32>,
+    outer_right: Option<u32>,
+    outer_bottom: Option<u32>,
+    outer_left: Option<u32>,
+    smart: Option<bool>,
+    #[serde(default)]
+    desktop: HashMap<String, Spanned<RawGaps>>,
+}
+
+impl RawGaps {
+    /// Apply the values given over gaps
+    fn apply(&self, gaps: &mut Gaps) {
+        if let Some(inner) = self.inner {
+            gaps.inner = inner;
+        }
+        if let Some(outer) = self.outer {
+            gaps.outer = Edges::all(outer);
+        }
+        let edges = vec![
+            (self.outer_top, &mut gaps.outer.top),
+            (self.outer_right, &mut gaps.outer.right),
+            (self.outer_bottom, &mut gaps.outer.bottom),
+            (self.outer_left, &mut gaps.outer.left),
+        ];
+        for (raw, edge) in edges {
+            if let Some(gap) = raw {
+                *edge = gap;
+            }
+        }
+        if let Some(smart) = self.smart {
+            gaps.smart = smart;
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBorder {
+    width: Option<u32>,
+    focused: Option<Spanned<String>>,
+    unfocused: Option<Spanned<String>>,
+    urgent: Option<Spanned<String>>,
+    title_height: Option<u32>,
+    title_color: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBind {
+    key: Spanned<String>,
+    command: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    class: Option<String>,
+    instance: Option<String>,
+    title: Option<String>,
+    desktop: Option<Spanned<usize>>,
+    floating: Option<bool>,
+}
+
+impl Config {
+    /// $XDG_CONFIG_HOME/worm/config.toml, falling back to ~/.config
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("worm").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let source = fs::read_to_string(path).map_err(|e| ConfigError {
+            path: path.to_path_buf(),
+            position: None,
+            message: e.to_string(),
+        })?;
+        let mut config = Config::parse(&source).map_err(|(position, message)| ConfigError {
+            path: path.to_path_buf(),
+            position,
+            message,
+        })?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Parse the contents of a config file, errors carry the line and column
+    /// of the offending value when it is known
+    pub fn parse(source: &str) -> Result<Config, (Option<(usize, usize)>, String)> {
+        let raw: RawConfig = toml::from_str(source).map_err(|e| {
+            let position = e.line_col().map(|(l, c)| (l + 1, c + 1));
+            (position, e.to_string())
+        })?;
+
+        let error_at = |start: usize, message: String| (Some(position(source, start)), message);
+        let mut config = Config::default();
+
+        if let Some(desktops) = raw.desktops {
+            if desktops.get_ref().is_empty() {
+                return Err(error_at(desktops.start(), "at least one desktop is needed".into()));
+            }
+            config.desktops = desktops.into_inner();
+        }
+
+        if let Some(layout) = raw.layout {
+            config.layout = layout
+                .get_ref()
+                .parse()
+                .map_err(|e| error_at(layout.start(), e))?;
+        }
+
+        if let Some(modifier) = raw.mouse_modifier {
+            config.mouse_modifier = Modifier::from_name(modifier.get_ref())
+                .map(|m| m.get_mod_mask())
+                .ok_or_else(|| {
+                    error_at(
+                        modifier.start(),
+                        format!("unknown modifier \"{}\"", modifier.get_ref()),
+                    )
+                })?;
+        }
+
+        if let Some(focus) = raw.focus {
+            config.focus_model = focus
+                .get_ref()
+                .parse()
+                .map_err(|e| error_at(focus.start(), e))?;
+        }
+
+        if let Some(num_master) = raw.tile.num_master {
+            config.tile.num_master = num_master;
+        }
+        if let Some(master_fact) = raw.tile.master_fact {
+            let fact = *master_fact.get_ref();
+            if !(fact > 0.0 && fact < 1.0) {
+                return Err(error_at(
+                    master_fact.start(),
+                    "master_fact must be between 0 and 1".into(),
+                ));
+            }
+            config.tile.master_fact = fact;
+        }
+
+        if let Some(size_hints) = raw.tile.size_hints {
+            config.tile.size_hints = size_hints;
+        }
+
+        raw.gaps.apply(&mut config.tile.gaps);
+        for (name, desktop) in raw.gaps.desktop.iter() {
+            if !config.desktops.contains(name) {
+                return Err(error_at(
+                    desktop.start(),
+                    format!("there is no desktop named \"{}\"", name),
+                ));
+            }
+            if !desktop.get_ref().desktop.is_empty() {
+                return Err(error_at(
+                    desktop.start(),
+                    "desktop gaps cannot be nested".into(),
+                ));
+            }
+            let mut gaps = config.tile.gaps;
+            desktop.get_ref().apply(&mut gaps);
+            config.desktop_gaps.insert(name.clone(), gaps);
+        }
+
+        if let Some(width) = raw.border.width {
+            config.border.width = width;
+        }
+        if let Some(height) = raw.border.title_height {
+            config.border.title_height = height;
+        }
+        let colors = vec![
+            (raw.border.focused, &mut config.border.focused),
+            (raw.border.unfocused, &mut config.border.unfocused),
+            (raw.border.urgent, &mut config.border.urgent),
+            (raw.border.title_color, &mut config.border.title_color),
+        ];
+        for (raw_color, color) in colors {
+            if let Some(c) = raw_color {
+                *color = parse_color(c.get_ref()).map_err(|e| error_at(c.start(), e))?;
+            }
+        }
+
+        // Bindings given in the file replace the defaults entirely
+        if let Some(binds) = raw.bind {
+            config.binds.key_map.clear();
+            for bind in binds {
+                let key: Key = bind
+                    .key
+                    .get_ref()
+                    .parse()
+                    .map_err(|e| error_at(bind.key.start(), e))?;
+                let command: Command = bind
+                    .command
+                    .get_ref()
+                    .parse()
+                    .map_err(|e| error_at(bind.command.start(), e))?;
+                if config.binds.key_map.insert(key, command).is_some() {
+                    return Err(error_at(
+                        bind.key.start(),
+                        format!("\"{}\" is bound more than once", bind.key.get_ref()),
+                    ));
+                }
+            }
+        }
+
+        for rule in raw.rule {
+            let desktop = match rule.desktop {
+                Some(d) if *d.get_ref() >= config.desktops.len() => {
+                    return Err(error_at(
+                        d.start(),
+                        format!("there is no desktop {}", d.get_ref()),
+                    ))
+                }
+                Some(d) => Some(d.into_inner()),
+                None => None,
+            };
+            config.rules.push(Rule {
+                class: rule.class,
+                instance: rule.instance,
+                title: rule.title,
+                desktop,
+                floating: rule.floating,
+            });
+        }
+
+        Ok(config)
+    }
+
+    /// Tiling settings of the desktop with the given name
+    pub fn tile_settings(&self, desktop: &str) -> TileSettings {
+        TileSettings {
+            gaps: self
+                .desktop_gaps
+                .get(desktop)
+                .copied()
+                .unwrap_or(self.tile.gaps),
+            ..self.tile
+        }
+    }
+
+    /// Settings of every configured desktop, in order
+    pub fn desktop_tile_settings(&self) -> Vec<TileSettings> {
+        self.desktops.iter().map(|d| self.tile_settings(d)).collect()
+    }
+
+    /// The first rule matching a client, if any
+    pub fn rule_for(&self, client: &Client) -> Option<&Rule> {
+        self.rules.iter().find(|r| r.matches(client))
+    }
+}
+
+/// Parse colors written as "#rrggbb"
+fn parse_color(color: &str) -> Result<Color, String> {
+    let invalid = || format!("\"{}\" is not a color, expected \"#rrggbb\"", color);
+    if !color.starts_with('#') || color.len() != 7 {
+        return Err(invalid());
+    }
+    Color::from_str_radix(&color[1..], 16).map_err(|_| invalid())
+}
+
+/// Line and column, starting at 1, of a byte offset into source
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_binds() {
+        let config = Config::parse(
+            r##"
+desktops = ["web", "code"]
+focus = "sloppy"
+mouse_modifier = "Super"
+
+[tile]
+master_fact = 0.6
+
+[gaps]
+inner = 4
+outer = 8
+smart = true
+
+[gaps.desktop.code]
+outer_top = 20
+
+[border]
+focused = "#ff8800"
+title_height = 0
+
+[[bind]]
+key = "Mod4+Shift+Return"
+command = "focus-desktop 1"
+"##,
+        )
+        .unwrap_or_else(|(_, e)| panic!("{}", e));
+
+        assert_eq!(config.desktops, vec!["web", "code"]);
+        assert_eq!(config.tile.gaps.inner, 4);
+        assert!(config.tile.gaps.smart);
+        let code = config.tile_settings("code").gaps;
+        assert_eq!(code.outer.top, 20);
+        assert_eq!(code.outer.left, 8);
+        assert_eq!(config.tile_settings("web").gaps.outer.top, 8);
+        assert_eq!(config.tile.num_master, 1);
+        assert_eq!(config.border.focused, 0xff8800);
+        assert_eq!(config.border.title_height, 0);
+        assert_eq!(config.binds.key_map.len(), 1);
+        assert_eq!(config.focus_model, FocusModel::Sloppy);
+        assert_eq!(config.mouse_modifier, Modifier::Mod4.get_mod_mask());
+    }
+
+    #[test]
+    fn errors_point_at_offending_line() {
+        let result = Config::parse(
+            r#"
+[[bind]]
+key = "Mod1+h"
+command = "focus-direction sideways"
+"#,
+        );
+        match result {
+            Err((position, message)) => {
+                assert_eq!(position, Some((4, 11)));
+                assert!(message.contains("sideways"));
+            }
+            Ok(_) => panic!("invalid command was accepted"),
+        }
+    }
+}
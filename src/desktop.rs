@@ -1,45 +1,339 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::backend::Backend;
+use crate::client::Client;
 use crate::command::Direction;
-use crate::layout::Layout;
+use crate::config::BorderConfig;
+use crate::layout::{Layout, TileSettings};
+use crate::rect::{self, Rect};
+use crate::session::{SavedDesktop, SavedWindow, Session};
 use crate::x;
-use crate::Screen;
+
+/// Bounds of the master factor, so neither column disappears entirely
+const MIN_MASTER_FACT: f32 = 0.05;
+const MAX_MASTER_FACT: f32 = 0.95;
 
 pub struct Desktops {
     pub desktops: Vec<Desktop>,
+    /// Desktop shown on the focused monitor
     pub focused_desktop: usize,
+    pub monitors: Vec<Monitor>,
+    pub focused_monitor: usize,
+    clients: HashMap<x::WindowId, ClientLocation>,
+}
+
+/// A monitor and the desktop it shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Monitor {
+    pub screen: Rect,
+    pub desktop: usize,
+}
+
+/// Where a managed window lives, as indices into `Desktops::desktops` and
+/// that desktop's windows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientLocation {
+    pub desktop: usize,
+    pub index: usize,
 }
 
 pub struct Desktop {
     name: String,
     active: bool,
     layout: Layout,
-    windows: Vec<x::Window>,
+    settings: TileSettings,
+    border: BorderConfig,
+    windows: Vec<Client>,
     pub focused_window: Option<usize>,
     pub focused_last: Option<usize>,
-    connection: Rc<x::Connection>,
-    screen: Screen,
+    /// Whether input focus is on this desktop, only then is its focused
+    /// window given focus and painted as focused
+    has_focus: bool,
+    connection: Rc<dyn Backend>,
+    screen: Rect,
 }
 
 impl Desktops {
-    pub fn new(desktops: Vec<Desktop>, focused_desktop: usize) -> Desktops {
-        Desktops {
+    /// Show the first desktops on the monitors with the given screens, in
+    /// order. Monitors beyond the number of desktops are left unused
+    pub fn new(desktops: Vec<Desktop>, screens: &[Rect]) -> Desktops {
+        let mut desktops = Desktops {
             desktops,
-            focused_desktop,
+            focused_desktop: 0,
+            monitors: Vec::new(),
+            focused_monitor: 0,
+            clients: HashMap::new(),
+        };
+        let count = screens.len().min(desktops.desktops.len());
+        for (i, screen) in screens.iter().take(count).enumerate() {
+            desktops.monitors.push(Monitor {
+                screen: *screen,
+                desktop: i,
+            });
+            desktops.desktops[i].active = true;
+            desktops.desktops[i].screen = *screen;
+        }
+        desktops.desktops[0].has_focus = true;
+        for i in 0..desktops.desktops.len() {
+            desktops.reindex(i);
         }
+        desktops.publish_desktops();
+        desktops.connection().set_current_desktop(0);
+        desktops
     }
 
     pub fn contains(&self, window: &x::Window) -> bool {
-        self.desktops.iter().any(|d| d.contains(window))
+        self.clients.contains_key(&window.as_xcb_window())
     }
 
-    pub fn add_window(&mut self, window: x::Window) {
-        self.desktops[self.focused_desktop].add_window(window);
+    /// Look up which desktop and position a managed window has
+    pub fn locate(&self, window: &x::Window) -> Option<ClientLocation> {
+        self.clients.get(&window.as_xcb_window()).copied()
     }
 
+    /// Add a window to the desktop at index, its frame is only mapped if that
+    /// desktop is visible
+    pub fn add_window_to(&mut self, desktop: usize, client: Client) {
+        let desktop = desktop.min(self.desktops.len() - 1);
+        self.desktops[desktop].add_window(client);
+        self.reindex(desktop);
+        self.publish_clients();
+        self.refocus(desktop);
+    }
+
+    /// Remove a window from whichever desktop it is on, putting it back on
+    /// the root
     pub fn remove_window(&mut self, window: &x::Window) {
-        let window_index = self.desktops[self.focused_desktop].get_window_index(window);
-        self.desktops[self.focused_desktop].remove_window(window_index);
+        let location = match self.clients.remove(&window.as_xcb_window()) {
+            Some(l) => l,
+            None => return,
+        };
+        if let Some(client) = self.desktops[location.desktop].remove_window(location.index) {
+            self.connection()
+                .destroy_frame(&client.frame, &client.window);
+        }
+        self.reindex(location.desktop);
+        self.publish_clients();
+        self.refocus(location.desktop);
+    }
+
+    /// Desktops on other monitors take focus when their windows change, give
+    /// it back to the focused monitor
+    fn refocus(&mut self, changed: usize) {
+        if changed != self.focused_desktop && self.desktops[changed].active {
+            self.update_focus();
+        }
+    }
+
+    /// Re-read a changed property of a managed window
+    pub fn update_property(&mut self, window: &x::Window, property: x::Property) {
+        let connection = self.desktops[self.focused_desktop].connection.clone();
+        let location = match self.locate(window) {
+            Some(l) => l,
+            None => return,
+        };
+        let desktop = &mut self.desktops[location.desktop];
+        let client = &mut desktop.windows[location.index];
+        client.update_property(&*connection, property);
+        match property {
+            x::Property::Name => client.draw_title(&*connection, desktop.border.title_color),
+            x::Property::Hints => desktop.paint_borders(),
+            x::Property::NormalHints => desktop.apply_layout(),
+            _ => {}
+        }
+    }
+
+    /// Look up the managed window inside a frame
+    fn locate_frame(&self, frame: &x::Window) -> Option<ClientLocation> {
+        self.desktops.iter().enumerate().find_map(|(desktop, d)| {
+            d.windows
+                .iter()
+                .position(|c| c.frame.as_xcb_window() == frame.as_xcb_window())
+                .map(|index| ClientLocation { desktop, index })
+        })
+    }
+
+    /// Redraw the title bar of the window in frame after it was exposed
+    pub fn redraw_title(&self, frame: &x::Window) {
+        if let Some(location) = self.locate_frame(frame) {
+            let desktop = &self.desktops[location.desktop];
+            let client = &desktop.windows[location.index];
+            client.draw_title(&*desktop.connection, desktop.border.title_color);
+        }
+    }
+
+    /// Focus the window in frame
+    pub fn focus_frame(&mut self, frame: &x::Window) {
+        if let Some(location) = self.locate_frame(frame) {
+            let window = self.desktops[location.desktop].windows[location.index].window;
+            self.activate_window(&window);
+        }
+    }
+
+    /// Focus the window in frame and take it out of the layout so it can be
+    /// dragged around. Returns the geometry of the frame, or None if the
+    /// window is unmanaged or fullscreen
+    pub fn start_drag(&mut self, frame: &x::Window) -> Option<Rect> {
+        self.focus_frame(frame);
+        let location = self.locate_frame(frame)?;
+
+        let desktop = &mut self.desktops[location.desktop];
+        let client = &desktop.windows[location.index];
+        if client.fullscreen {
+            return None;
+        }
+        if desktop.layout != Layout::Float && !client.floating {
+            desktop.toggle_floating(location.index);
+            self.publish_clients();
+        }
+        Some(self.desktops[location.desktop].windows[location.index].geometry())
+    }
+
+    /// Move or resize the frame of a window being dragged, within what its
+    /// size hints allow
+    pub fn drag_window(&mut self, frame: &x::Window, geometry: Rect) {
+        let location = match self.locate_frame(frame) {
+            Some(l) => l,
+            None => return,
+        };
+        let desktop = &mut self.desktops[location.desktop];
+        let client = &mut desktop.windows[location.index];
+        let geometry = client.constrain(geometry);
+        if geometry != client.geometry() {
+            let title_height = client.title_height;
+            client.configure(&*desktop.connection, geometry, title_height);
+        }
+    }
+
+    /// Record the geometry of a managed window reported by the X server
+    pub fn update_geometry(&mut self, window: &x::Window) {
+        let location = match self.locate(window) {
+            Some(l) => l,
+            None => return,
+        };
+        let desktop = &mut self.desktops[location.desktop];
+        let floating_layout = desktop.layout() == Layout::Float;
+        let client = &mut desktop.windows[location.index];
+        let floating = floating_layout || client.floating;
+        client.update_geometry(window, floating);
+    }
+
+    /// Re-apply the layout of the desktop a managed window is on so it keeps
+    /// its place, returns false if the window is unmanaged or free to float
+    pub fn enforce_layout(&mut self, window: &x::Window) -> bool {
+        let location = match self.locate(window) {
+            Some(l) => l,
+            None => return false,
+        };
+        let desktop = &mut self.desktops[location.desktop];
+        let client = &desktop.windows[location.index];
+        if (desktop.layout() == Layout::Float || client.floating) && !client.fullscreen {
+            return false;
+        }
+        // The client still expects to hear back, even if nothing changed
+        if client.fullscreen || client.tiled_geometry.is_some() {
+            desktop.connection.configure_window(
+                &client.window,
+                &x::WindowChanges::from(client.window.rect()),
+            );
+        } else {
+            desktop.apply_layout();
+        }
+        true
+    }
+
+    /// Make a managed window cover its whole monitor, or give it back its
+    /// place in the layout. None toggles
+    pub fn set_fullscreen(&mut self, window: &x::Window, fullscreen: Option<bool>) {
+        if let Some(location) = self.locate(window) {
+            self.desktops[location.desktop].set_fullscreen(location.index, fullscreen);
+            self.publish_clients();
+        }
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        if let Some(window) = self.get_focused_window() {
+            self.set_fullscreen(&window, None);
+        }
+    }
+
+    /// Take the focused window out of the layout, or put it back
+    pub fn toggle_floating(&mut self) {
+        let desktop = &mut self.desktops[self.focused_desktop];
+        if let Some(index) = desktop.focused_window {
+            desktop.toggle_floating(index);
+            self.publish_clients();
+        }
+    }
+
+    /// Move and resize the frame of a managed floating window as it asked,
+    /// returns false if the window is unmanaged
+    pub fn configure_floating(
+        &mut self,
+        window: &x::Window,
+        window_changes: &x::WindowChanges,
+    ) -> bool {
+        let location = match self.locate(window) {
+            Some(l) => l,
+            None => return false,
+        };
+        let desktop = &mut self.desktops[location.desktop];
+        let client = &mut desktop.windows[location.index];
+        let title_height = client.title_height;
+        let mut geometry = client.geometry();
+        // Positions asked for are where the window goes on the root
+        if window_changes.moved {
+            geometry.x = window_changes.x;
+            geometry.y = window_changes.y;
+        }
+        geometry.width = window_changes.width;
+        geometry.height = window_changes.height.saturating_add(title_height);
+        let geometry = client.constrain(geometry);
+        client.configure(&*desktop.connection, geometry, title_height);
+        true
+    }
+
+    /// Refresh the registry entries for every window on a desktop after its
+    /// windows were added, removed or reordered
+    fn reindex(&mut self, desktop: usize) {
+        let connection = &*self.desktops[desktop].connection;
+        for (index, client) in self.desktops[desktop].windows.iter().enumerate() {
+            let old = self.clients.insert(
+                client.window.as_xcb_window(),
+                ClientLocation { desktop, index },
+            );
+            if old.map(|l| l.desktop) != Some(desktop) {
+                connection.set_window_desktop(&client.window, desktop);
+            }
+        }
+    }
+
+    /// Publish the managed windows for bars and pagers
+    fn publish_clients(&self) {
+        let windows: Vec<x::WindowId> = self
+            .managed_windows()
+            .iter()
+            .map(|w| w.as_xcb_window())
+            .collect();
+        let mut stacking: Vec<(u8, x::WindowId)> = self
+            .desktops
+            .iter()
+            .flat_map(|d| {
+                d.windows
+                    .iter()
+                    .map(move |c| (d.layout.stack_level(c), c.window.as_xcb_window()))
+            })
+            .collect();
+        stacking.sort_by_key(|&(level, _)| level);
+        let stacking: Vec<x::WindowId> = stacking.into_iter().map(|(_, w)| w).collect();
+        self.connection().set_client_list(&windows, &stacking);
+    }
+
+    /// Publish the number of desktops and their names
+    fn publish_desktops(&self) {
+        let names: Vec<String> = self.desktops.iter().map(|d| d.name.clone()).collect();
+        self.connection().set_desktop_names(&names);
     }
 
     pub fn layout(&self) -> Layout {
@@ -48,10 +342,334 @@ impl Desktops {
 
     pub fn change_layout(&mut self, layout: &Layout) {
         self.desktops[self.focused_desktop].change_layout(layout);
+        self.publish_clients();
+    }
+
+    /// Apply the layout of every visible desktop
+    pub fn apply_layouts(&mut self) {
+        for desktop in self.desktops.iter_mut() {
+            desktop.apply_layout();
+        }
+    }
+
+    /// Every managed window, on any desktop
+    pub fn managed_windows(&self) -> Vec<x::Window> {
+        self.desktops
+            .iter()
+            .flat_map(|d| d.windows.iter().map(|c| c.window))
+            .collect()
+    }
+
+    /// The frame of every managed window, on any desktop
+    pub fn managed_frames(&self) -> Vec<x::Window> {
+        self.desktops
+            .iter()
+            .flat_map(|d| d.windows.iter().map(|c| c.frame))
+            .collect()
+    }
+
+    /// Record where every window is so it can be restored after a restart
+    pub fn save_session(&self) -> Session {
+        let mut windows: Vec<SavedWindow> = self
+            .clients
+            .iter()
+            .map(|(window, location)| SavedWindow {
+                window: *window,
+                desktop: location.desktop,
+                index: location.index,
+                floating: self.desktops[location.desktop].windows[location.index].floating,
+            })
+            .collect();
+        windows.sort_by_key(|w| (w.desktop, w.index));
+
+        Session {
+            current_desktop: self.focused_desktop,
+            desktops: self
+                .desktops
+                .iter()
+                .map(|d| SavedDesktop {
+                    layout: d.layout,
+                    focused_window: d.focused_window,
+                })
+                .collect(),
+            windows,
+        }
+    }
+
+    /// Put back the layout and focus a desktop had before a restart
+    pub fn restore_desktop(&mut self, index: usize, saved: &SavedDesktop) {
+        let desktop = match self.desktops.get_mut(index) {
+            Some(d) => d,
+            None => return,
+        };
+        if let Some(focused) = saved.focused_window {
+            if focused < desktop.windows.len() {
+                desktop.focused_last = None;
+                desktop.focused_window = Some(focused);
+            }
+        }
+        if desktop.active && desktop.layout == Layout::Monocle {
+            for client in desktop.windows.iter_mut() {
+                client.unmap(&*desktop.connection);
+            }
+        }
+        desktop.layout = saved.layout;
+        desktop.apply_layout();
+        desktop.map_focused_monocle();
+        desktop.update_focus();
+        self.publish_clients();
+    }
+
+    /// Put every managed window back on the root mapped and stop listening
+    /// to them, used when worm exits so no window is left hidden
+    pub fn release_windows(&self) {
+        for desktop in self.desktops.iter() {
+            for client in desktop.windows.iter() {
+                desktop.connection.stop_window_events(&client.window);
+                desktop.connection.ungrab_keys(&client.window);
+                desktop
+                    .connection
+                    .destroy_frame(&client.frame, &client.window);
+                desktop.connection.map_window(&client.window);
+            }
+        }
     }
 
-    pub fn apply_layout(&mut self) {
-        self.desktops[self.focused_desktop].apply_layout();
+    /// Take on new desktop names and tiling settings without touching the
+    /// windows. Desktops are added as needed but only trailing empty hidden
+    /// ones are removed, so no window loses its desktop. settings holds the
+    /// settings of each named desktop
+    pub fn reconfigure(
+        &mut self,
+        names: &[String],
+        layout: Layout,
+        settings: &[TileSettings],
+        border: BorderConfig,
+    ) {
+        while self.desktops.len() > names.len() {
+            let last = self.desktops.len() - 1;
+            if self.desktops[last].active || !self.desktops[last].windows.is_empty() {
+                break;
+            }
+            self.desktops.pop();
+        }
+
+        let connection = self.desktops[0].connection.clone();
+        for (i, name) in names.iter().enumerate() {
+            match self.desktops.get_mut(i) {
+                Some(desktop) => {
+                    desktop.name = name.clone();
+                    desktop.settings = settings[i];
+                    desktop.border = border;
+                }
+                None => self.desktops.push(Desktop::new(
+                    name,
+                    layout,
+                    settings[i],
+                    border,
+                    connection.clone(),
+                )),
+            }
+        }
+        self.publish_desktops();
+    }
+
+    /// Show the desktop at index on the focused monitor in place of the one
+    /// there. A desktop already shown on another monitor is focused there
+    pub fn focus_desktop(&mut self, index: usize) {
+        if index >= self.desktops.len() || index == self.focused_desktop {
+            return;
+        }
+        if let Some(monitor) = self.monitors.iter().position(|m| m.desktop == index) {
+            self.focus_monitor_at(monitor);
+            return;
+        }
+
+        let monitor = self.focused_monitor;
+        self.desktops[self.focused_desktop].hide();
+        self.monitors[monitor].desktop = index;
+        self.set_focused_desktop(index);
+        self.desktops[index].set_screen(self.monitors[monitor].screen);
+        self.desktops[index].show();
+        self.connection().set_current_desktop(index);
+    }
+
+    /// Match the monitors to the screens after outputs were added, removed or
+    /// changed resolution. The focused desktop stays in view, windows of
+    /// vanished monitors join the desktop on the last monitor left and new
+    /// monitors show the first hidden desktops
+    pub fn set_monitors(&mut self, screens: &[Rect]) {
+        let screens = &screens[..screens.len().min(self.desktops.len())];
+        if screens.is_empty()
+            || self
+                .monitors
+                .iter()
+                .map(|m| m.screen)
+                .eq(screens.iter().copied())
+        {
+            return;
+        }
+
+        let count = screens.len();
+        if self.focused_monitor >= count {
+            let last = count - 1;
+            let focused = self.monitors[self.focused_monitor].desktop;
+            self.monitors[self.focused_monitor].desktop = self.monitors[last].desktop;
+            self.monitors[last].desktop = focused;
+            self.focused_monitor = last;
+        }
+        let vanished: Vec<usize> = self
+            .monitors
+            .drain(count.min(self.monitors.len())..)
+            .map(|m| m.desktop)
+            .collect();
+
+        for (i, screen) in screens.iter().enumerate() {
+            match self.monitors.get_mut(i) {
+                Some(monitor) => {
+                    monitor.screen = *screen;
+                    self.desktops[monitor.desktop].set_screen(*screen);
+                }
+                None => {
+                    // There are at least as many desktops as monitors
+                    let desktop = match self.desktops.iter().position(|d| !d.active) {
+                        Some(d) => d,
+                        None => break,
+                    };
+                    self.monitors.push(Monitor {
+                        screen: *screen,
+                        desktop,
+                    });
+                    self.desktops[desktop].set_screen(*screen);
+                    self.desktops[desktop].show();
+                }
+            }
+        }
+
+        let last = self.monitors[self.monitors.len() - 1].desktop;
+        for desktop in vanished {
+            self.move_all_windows(desktop, last);
+            self.desktops[desktop].hide();
+        }
+
+        self.set_focused_desktop(self.monitors[self.focused_monitor].desktop);
+        self.connection().set_current_desktop(self.focused_desktop);
+        self.update_focus();
+    }
+
+    /// Move every window on the desktop at from to the desktop at to, pulling
+    /// floating ones onto its screen
+    fn move_all_windows(&mut self, from: usize, to: usize) {
+        if self.desktops[from].windows.is_empty() {
+            return;
+        }
+        while let Some(client) = self.desktops[from].take_window(0) {
+            self.desktops[to].receive_window(client);
+        }
+        self.desktops[to].clamp_floating();
+        self.desktops[to].apply_layout();
+        self.reindex(to);
+        self.publish_clients();
+    }
+
+    /// Move input focus to the desktop at index. The desktop losing it
+    /// repaints its focused window as unfocused
+    fn set_focused_desktop(&mut self, index: usize) {
+        if index == self.focused_desktop {
+            return;
+        }
+        self.desktops[self.focused_desktop].set_focus(false);
+        self.focused_desktop = index;
+        self.desktops[index].set_focus(true);
+    }
+
+    /// Focus the monitor delta places after the focused one, wrapping around
+    pub fn focus_monitor(&mut self, delta: isize) {
+        self.focus_monitor_at(self.monitor_offset(delta));
+    }
+
+    /// Move the focused window to the desktop shown on the monitor delta
+    /// places after the focused one
+    pub fn move_focused_to_monitor(&mut self, delta: isize) {
+        let desktop = self.monitors[self.monitor_offset(delta)].desktop;
+        self.move_focused_to_desktop(desktop, false);
+    }
+
+    fn monitor_offset(&self, delta: isize) -> usize {
+        let count = self.monitors.len() as isize;
+        (self.focused_monitor as isize + delta).rem_euclid(count) as usize
+    }
+
+    fn focus_monitor_at(&mut self, monitor: usize) {
+        if monitor == self.focused_monitor {
+            return;
+        }
+        self.focused_monitor = monitor;
+        self.set_focused_desktop(self.monitors[monitor].desktop);
+        self.connection().set_current_desktop(self.focused_desktop);
+        self.update_focus();
+    }
+
+    /// Move the focused window to the desktop at index, optionally following it
+    pub fn move_focused_to_desktop(&mut self, index: usize, follow: bool) {
+        if let Some(window) = self.get_focused_window() {
+            self.move_window_to_desktop(&window, index, follow);
+        }
+    }
+
+    /// Move a managed window to the desktop at index, optionally following it
+    pub fn move_window_to_desktop(&mut self, window: &x::Window, index: usize, follow: bool) {
+        let location = match self.locate(window) {
+            Some(l) => l,
+            None => return,
+        };
+        if index >= self.desktops.len() || index == location.desktop {
+            return;
+        }
+
+        let mut client = match self.desktops[location.desktop].take_window(location.index) {
+            Some(c) => c,
+            None => return,
+        };
+
+        // The window is visible until its new desktop gets shown
+        if !self.desktops[index].active {
+            client.unmap(self.connection());
+        }
+
+        self.desktops[index].receive_window(client);
+        self.reindex(index);
+        self.reindex(location.desktop);
+
+        let source = &mut self.desktops[location.desktop];
+        source.apply_layout();
+        source.map_focused_monocle();
+        self.update_focus();
+
+        if follow {
+            self.focus_desktop(index);
+        }
+    }
+
+    /// Show the desktop a managed window is on and focus the window
+    pub fn activate_window(&mut self, window: &x::Window) {
+        let location = match self.locate(window) {
+            Some(l) => l,
+            None => return,
+        };
+        self.focus_desktop(location.desktop);
+        self.desktops[location.desktop].focus_index(location.index);
+    }
+
+    /// Ask a managed window to close, it is unmanaged once it goes away
+    pub fn close_window(&mut self, window: &x::Window) {
+        if self.contains(window) {
+            self.connection().delete_window(window);
+        }
+    }
+
+    fn connection(&self) -> &dyn Backend {
+        &*self.desktops[self.focused_desktop].connection
     }
 
     // TODO: Cleanup is needed
@@ -71,76 +689,33 @@ impl Desktops {
     }
 
     fn focus_window_tile(&mut self, direction: &Direction) {
-        match direction {
-            Direction::Up => {
-                // TODO: This would need to be compared to num_master global
-                // config variable if that gets supported
-                if self.desktops[self.focused_desktop].focused_window == Some(0) {
-                    return;
-                } else if self.desktops[self.focused_desktop].focused_window.unwrap() - 1 == 0 {
-                    return;
-                }
-                self.desktops[self.focused_desktop].focused_last =
-                    self.desktops[self.focused_desktop].focused_window;
-                match self.desktops[self.focused_desktop].focused_window.as_mut() {
-                    Some(i) => *i -= 1,
-                    None => {}
-                };
-                self.desktops[self.focused_desktop].update_focus();
-            }
-            Direction::Down => {
-                if self.desktops[self.focused_desktop].focused_window == Some(0) {
-                    return;
-                } else if self.desktops[self.focused_desktop].focused_window.unwrap() + 1
-                    == self.desktops[self.focused_desktop].windows.len()
-                {
-                    return;
-                }
-                self.desktops[self.focused_desktop].focused_last =
-                    self.desktops[self.focused_desktop].focused_window;
-                match self.desktops[self.focused_desktop].focused_window.as_mut() {
-                    Some(i) => *i += 1,
-                    None => {}
-                };
-                self.desktops[self.focused_desktop].update_focus();
-            }
-            // TODO: This would need to be compared to num_master global
-            // config variable if that gets supported, not 0
-            Direction::Left => {
-                if self.desktops[self.focused_desktop].focused_window == Some(0) {
-                    return;
-                }
-                self.desktops[self.focused_desktop].focused_last =
-                    self.desktops[self.focused_desktop].focused_window;
-                match self.desktops[self.focused_desktop].focused_window.as_mut() {
-                    Some(i) => *i = 0,
-                    None => {}
-                };
-                self.desktops[self.focused_desktop].update_focus();
-            }
-            // TODO: This would need to be compared to num_master global
-            // config variable if that gets supported, not 0
-            Direction::Right => {
-                if self.desktops[self.focused_desktop].focused_window != Some(0) {
-                    return;
-                } else if self.desktops[self.focused_desktop].windows.len() <= 1 {
-                    return;
-                }
-                let last = self.desktops[self.focused_desktop]
-                    .focused_last
-                    .unwrap_or(1);
-                match self.desktops[self.focused_desktop].focused_window.as_mut() {
-                    Some(i) => *i = last,
-                    None => {}
-                };
-                self.desktops[self.focused_desktop].focused_last = Some(0);
-                self.update_focus();
-            }
-        };
+        self.desktops[self.focused_desktop].focus_window_tile(direction);
+    }
+
+    pub fn change_master_count(&mut self, delta: isize) {
+        self.desktops[self.focused_desktop].change_master_count(delta);
+    }
+
+    pub fn change_master_factor(&mut self, delta: f32) {
+        self.desktops[self.focused_desktop].change_master_factor(delta);
+    }
+
+    pub fn change_inner_gap(&mut self, delta: i32) {
+        self.desktops[self.focused_desktop].change_inner_gap(delta);
+    }
+
+    pub fn change_outer_gap(&mut self, delta: i32) {
+        self.desktops[self.focused_desktop].change_outer_gap(delta);
+    }
+
+    pub fn toggle_gaps(&mut self) {
+        self.desktops[self.focused_desktop].toggle_gaps();
     }
 
     pub fn move_window_tile(&mut self, direction: &Direction) {
-        self.desktops[self.focused_desktop].move_window(direction);
+        let desktop = self.focused_desktop;
+        self.desktops[desktop].move_window(direction);
+        self.reindex(desktop);
     }
 
     fn focus_window_monocle(&mut self, direction: &Direction) {
@@ -157,7 +732,7 @@ impl Desktops {
         //self.apply_layout();
     }
 
-    fn update_focus(&self) {
+    fn update_focus(&mut self) {
         self.desktops[self.focused_desktop].update_focus();
     }
 
@@ -165,55 +740,81 @@ impl Desktops {
         self.desktops[self.focused_desktop].get_focused_window()
     }
 
+    /// Ask the focused window to close, it is unmanaged once it goes away
     pub fn delete_focused_window(&mut self) {
         self.desktops[self.focused_desktop].delete_focused_window();
-        self.update_focus();
     }
 }
 
 impl Desktop {
+    /// A hidden desktop, it gets a screen once shown on a monitor
     pub fn new(
-        name: &String,
-        active: bool,
+        name: &str,
         layout: Layout,
-        windows: Vec<x::Window>,
-        connection: Rc<x::Connection>,
-        screen: &Screen,
+        settings: TileSettings,
+        border: BorderConfig,
+        connection: Rc<dyn Backend>,
     ) -> Desktop {
         Desktop {
-            name: name.clone(),
-            active,
+            name: name.to_owned(),
+            active: false,
             layout,
-            windows: windows,
+            settings,
+            border,
+            windows: Vec::new(),
             focused_window: None,
             focused_last: None,
+            has_focus: false,
             connection,
-            screen: screen.clone(),
+            screen: Rect::default(),
         }
     }
 
-    fn contains(&self, window: &x::Window) -> bool {
-        self.windows.iter().any(|w| w == window)
-    }
-
-    fn add_window(&mut self, window: x::Window) {
+    fn add_window(&mut self, client: Client) {
         if self.focused_window.is_none() {
             self.focused_window = Some(0);
             self.focused_last = None;
         }
-        self.windows.push(window);
+        self.windows.push(client);
+        self.restack();
         self.apply_layout();
-        if self.layout == Layout::Monocle {
-            self.connection.map_window(&self.get_focused_window().unwrap());
-        }
+        self.map_focused_monocle();
         self.update_focus();
     }
 
-    fn remove_window(&mut self, index: Option<usize>) -> Option<x::Window> {
-        match index {
-            Some(i) => Some(self.windows.remove(i)),
-            None => None,
+    /// Remove the window at index, keeping focus on the same window if it
+    /// was not the one removed
+    fn remove_window(&mut self, index: usize) -> Option<Client> {
+        let focused = self.focused_window == Some(index);
+        let window = self.take_window(index)?;
+        self.apply_layout();
+        if focused {
+            self.map_focused_monocle();
+            self.update_focus();
+        }
+        Some(window)
+    }
+
+    /// Take the window at index off the desktop without laying it out again,
+    /// focus moves on as in take_focused_window if it was focused
+    fn take_window(&mut self, index: usize) -> Option<Client> {
+        if index >= self.windows.len() {
+            return None;
         }
+        if self.focused_window == Some(index) {
+            return self.take_focused_window();
+        }
+
+        let window = self.windows.remove(index);
+        self.focused_window = self
+            .focused_window
+            .map(|i| if i > index { i - 1 } else { i });
+        self.focused_last = match self.focused_last {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            last => last,
+        };
+        Some(window)
     }
 
     fn layout(&self) -> Layout {
@@ -224,30 +825,120 @@ impl Desktop {
         if self.layout == *layout {
             return;
         }
-        self.layout = layout.clone();
+        self.layout = *layout;
+        // Monocle only keeps the focused window mapped
+        if self.active && self.layout == Layout::Monocle {
+            for (i, client) in self.windows.iter_mut().enumerate() {
+                if self.focused_window != Some(i) {
+                    client.unmap(&*self.connection);
+                }
+            }
+        }
         self.apply_layout();
     }
 
     fn apply_layout(&mut self) {
-        /* TODO: Set and check for active desktops everywhere
         if !self.active {
-            return
+            return;
         }
-        */
 
-        self.layout
-            .apply(&self.connection, &self.windows, &self.screen);
+        self.layout.apply(
+            &*self.connection,
+            &mut self.windows,
+            &self.screen,
+            &self.settings,
+            &self.border,
+        );
+        self.paint_borders();
 
         // TODO: Set focus on a window maybe
     }
 
+    /// Move the desktop onto a monitor's screen, pulling floating windows
+    /// that would be off it back on
+    fn set_screen(&mut self, screen: Rect) {
+        if self.screen == screen {
+            return;
+        }
+        self.screen = screen;
+        self.clamp_floating();
+        self.apply_layout();
+    }
+
+    /// Pull floating windows that are off the screen back onto it
+    fn clamp_floating(&mut self) {
+        let border = self.border.width.saturating_mul(2);
+        let area = self.screen.inset(0, border, border, 0);
+        let floating_layout = self.layout == Layout::Float;
+        for client in self.windows.iter_mut() {
+            if !floating_layout && !client.floating {
+                continue;
+            }
+            let geometry = client.geometry().clamp_into(&area).at_least_min_size();
+            if geometry != client.geometry() {
+                let title_height = client.title_height;
+                client.configure(&*self.connection, geometry, title_height);
+            }
+        }
+    }
+
+    /// Unmap every visible window and mark the desktop inactive
+    fn hide(&mut self) {
+        if !self.active {
+            return;
+        }
+        self.active = false;
+
+        for client in self.windows.iter_mut() {
+            client.unmap(&*self.connection);
+        }
+    }
+
+    /// Mark the desktop active, map and lay out its windows and restore focus
+    fn show(&mut self) {
+        if self.active {
+            return;
+        }
+        self.active = true;
+
+        self.apply_layout();
+        self.map_focused_monocle();
+        self.update_focus();
+    }
+
     // TODO: Maybe propogate option
-    fn update_focus(&self) {
-        if let Some(focused) = self.get_focused_window() {
-            self.connection.focus_window(focused);
-        } else {
+    fn update_focus(&mut self) {
+        if !self.active {
             return;
         }
+        if let Some(focused) = self.get_focused_window().filter(|_| self.has_focus) {
+            self.connection.focus_window(focused);
+        }
+        self.paint_borders();
+    }
+
+    fn set_focus(&mut self, has_focus: bool) {
+        self.has_focus = has_focus;
+        self.paint_borders();
+    }
+
+    /// Color each border and title bar by whether its window is focused or
+    /// urgent, sending only the colors that changed
+    fn paint_borders(&mut self) {
+        for (i, client) in self.windows.iter_mut().enumerate() {
+            let color = if self.has_focus && self.focused_window == Some(i) {
+                self.border.focused
+            } else if client.urgent {
+                self.border.urgent
+            } else {
+                self.border.unfocused
+            };
+            if client.border_color != Some(color) {
+                self.connection.set_border_color(&client.frame, color);
+                client.border_color = Some(color);
+                client.draw_title(&*self.connection, self.border.title_color);
+            }
+        }
     }
 
     fn cycle_window_forward(&mut self) {
@@ -255,23 +946,20 @@ impl Desktop {
             panic!("Trying to cylce on non monocle layout");
         }
 
-        if let Some(focused) = self.get_focused_window() {
+        if self.focused_window.is_some() {
             if self.focused_window == Some(self.windows.len() - 1) {
-                self.connection.unmap_window(&focused);
+                self.unmap_focused();
                 self.focused_last = self.focused_window;
                 self.focused_window = Some(0);
-                self.connection.map_window(&self.get_focused_window().unwrap());
+                self.map_focused();
             } else {
-                self.connection.unmap_window(&focused);
+                self.unmap_focused();
                 self.focused_last = self.focused_window;
-                match self.focused_window.as_mut() {
-                    Some(i) => *i += 1,
-                    None => {}
+                if let Some(i) = self.focused_window.as_mut() {
+                    *i += 1
                 };
-                self.connection.map_window(&self.get_focused_window().unwrap());
+                self.map_focused();
             }
-        } else {
-            return;
         }
     }
 
@@ -280,39 +968,44 @@ impl Desktop {
             panic!("Trying to cylce on non monocle layout");
         }
 
-        if let Some(focused) = self.get_focused_window() {
+        if self.focused_window.is_some() {
             if self.focused_window == Some(0) {
-                self.connection.unmap_window(&focused);
+                self.unmap_focused();
                 self.focused_last = Some(0);
-                match self.focused_window.as_mut() {
-                    Some(i) => *i = self.windows.len() - 1,
-                    None => {}
+                if let Some(i) = self.focused_window.as_mut() {
+                    *i = self.windows.len() - 1
                 };
-                self.connection.map_window(&self.get_focused_window().unwrap());
+                self.map_focused();
             } else {
-                self.connection.unmap_window(&focused);
+                self.unmap_focused();
                 self.focused_last = self.focused_window;
-                match self.focused_window.as_mut() {
-                    Some(i) => *i -= 1,
-                    None => {}
+                if let Some(i) = self.focused_window.as_mut() {
+                    *i -= 1
                 };
-                self.connection.map_window(&self.get_focused_window().unwrap());
+                self.map_focused();
             }
-        } else {
-            return;
         }
     }
 
     fn get_focused_window(&self) -> Option<x::Window> {
-        match self.focused_window {
-            Some(i) => Some(self.windows[i]),
-            None => None,
+        self.focused_window.map(|i| self.windows[i].window)
+    }
+
+    fn map_focused(&mut self) {
+        if let Some(i) = self.focused_window {
+            self.windows[i].map(&*self.connection);
+        }
+    }
+
+    fn unmap_focused(&mut self) {
+        if let Some(i) = self.focused_window {
+            self.windows[i].unmap(&*self.connection);
         }
     }
 
     fn get_window_index(&self, window: &x::Window) -> Option<usize> {
-        for (i, win) in self.windows.iter().enumerate() {
-            if win.as_xcb_window() == window.as_xcb_window() {
+        for (i, client) in self.windows.iter().enumerate() {
+            if client.window.as_xcb_window() == window.as_xcb_window() {
                 return Some(i);
             }
         }
@@ -321,110 +1014,772 @@ impl Desktop {
 
     fn delete_focused_window(&mut self) {
         if let Some(focused) = self.get_focused_window() {
-            let last: x::Window;
-            if self.focused_last != None {
-                last = self.windows[self.focused_last.unwrap()];
-            } else if self.windows.len() >= 1 {
-                //last = self.windows[self.focused_window.unwrap() - 1];
-                last = self.windows[0];
-            } else {
-                // Never used but needs to be set for the borrow later. Root window should never be invalidated
-                last = self.connection.root_window();
-            }
             self.connection.delete_window(&focused);
             self.connection.flush();
-            self.remove_window(self.focused_window);
+        }
+    }
 
-            if self.windows.len() == 0 {
-                self.focused_window = None;
-                self.focused_last = None;
-            } else if self.windows.len() == 1 {
-                self.focused_window = Some(0);
-                self.focused_last = None;
-            } else {
-                println!("SETTING TO: {:?}", last);
-                self.focused_window = self.get_window_index(&last);
-                self.focused_last = None;
-            }
+    fn set_fullscreen(&mut self, index: usize, fullscreen: Option<bool>) {
+        let floating_layout = self.layout == Layout::Float;
+        let client = &mut self.windows[index];
+        let fullscreen = fullscreen.unwrap_or(!client.fullscreen);
+        if client.fullscreen == fullscreen {
+            return;
+        }
+        // Floating windows go back to where they are now afterwards
+        if fullscreen && (floating_layout || client.floating) {
+            client.floating_geometry = Some(client.geometry());
+        }
+        client.fullscreen = fullscreen;
+        self.connection
+            .set_fullscreen_state(&client.window, fullscreen);
+        self.apply_layout();
+    }
 
-            self.apply_layout();
-            if self.layout == Layout::Monocle && !self.focused_window.is_none() {
-                self.connection.map_window(&self.get_focused_window().unwrap());
+    fn toggle_floating(&mut self, index: usize) {
+        let client = &mut self.windows[index];
+        client.floating = !client.floating;
+        if client.floating {
+            // Back to where it last floated, or left where it was tiled
+            client.tiled_geometry = None;
+            let geometry = client.floating_geometry.unwrap_or(client.geometry());
+            if geometry != client.geometry() && !client.fullscreen {
+                let title_height = client.title_height;
+                client.configure(&*self.connection, geometry, title_height);
             }
-            self.update_focus();
+        }
+        self.apply_layout();
+    }
+
+    /// Focus the window at index, remembering the one focused before
+    fn focus_index(&mut self, index: usize) {
+        if index >= self.windows.len() || self.focused_window == Some(index) {
+            return;
+        }
+        if self.active && self.layout == Layout::Monocle {
+            self.unmap_focused();
+        }
+        self.focused_last = self.focused_window;
+        self.focused_window = Some(index);
+        self.map_focused_monocle();
+        self.update_focus();
+    }
+
+    /// Remove the focused window from the desktop, moving focus to the last
+    /// focused window or the first window if there is none
+    fn take_focused_window(&mut self) -> Option<Client> {
+        let focused = self.focused_window?;
+        let last = self
+            .focused_last
+            .filter(|&i| i != focused && i < self.windows.len())
+            .map(|i| self.windows[i].window);
+
+        let window = self.windows.remove(focused);
+
+        self.focused_last = None;
+        self.focused_window = if self.windows.is_empty() {
+            None
         } else {
+            last.and_then(|l| self.get_window_index(&l)).or(Some(0))
+        };
+
+        Some(window)
+    }
+
+    /// Add a window to the desktop and focus it
+    fn receive_window(&mut self, client: Client) {
+        if self.active && self.layout == Layout::Monocle {
+            self.unmap_focused();
+        }
+
+        self.focused_last = self.focused_window;
+        self.windows.push(client);
+        self.focused_window = Some(self.windows.len() - 1);
+        self.restack();
+
+        self.apply_layout();
+        self.map_focused_monocle();
+        self.update_focus();
+    }
+
+    /// Raise the floating and fullscreen windows again on the next layout,
+    /// a window that just joined may be stacked above them
+    fn restack(&mut self) {
+        for client in self.windows.iter_mut() {
+            client.raised = false;
+        }
+    }
+
+    /// Monocle layouts only map the focused window
+    fn map_focused_monocle(&mut self) {
+        if !self.active || self.layout != Layout::Monocle {
             return;
         }
+        self.map_focused();
     }
 
+    /// Swap the focused window with its neighbour in the tiling layout
     fn move_window(&mut self, direction: &Direction) {
-        if self.windows.is_empty() || self.windows.len() == 1 {
-            return;
+        let focused = match self.focused_window {
+            Some(i) => i,
+            None => return,
+        };
+        let target = match self.tile_neighbour(focused, direction) {
+            Some(t) => t,
+            None => return,
+        };
+
+        self.windows.swap(focused, target);
+        self.focused_last = Some(focused);
+        self.focused_window = Some(target);
+        self.apply_layout();
+    }
+
+    fn focus_window_tile(&mut self, direction: &Direction) {
+        let focused = match self.focused_window {
+            Some(i) => i,
+            None => return,
+        };
+        if let Some(target) = self.tile_neighbour(focused, direction) {
+            self.focused_last = Some(focused);
+            self.focused_window = Some(target);
+            self.update_focus();
         }
-        if self.focused_window.is_none() {
-            return;
+    }
+
+    /// Index of the window next to the one at index in the tiling layout.
+    /// Crossing between the master and stack columns goes back to the window
+    /// last focused in the other column. Floating and fullscreen windows are
+    /// not tiled, so they have no neighbours and are no one's neighbour
+    fn tile_neighbour(&self, index: usize, direction: &Direction) -> Option<usize> {
+        if self.layout == Layout::Float {
+            return None;
+        }
+        let tiled: Vec<usize> = (0..self.windows.len())
+            .filter(|&i| !self.windows[i].floating && !self.windows[i].fullscreen)
+            .collect();
+        // Positions among the tiled windows rather than indices
+        let position = tiled.iter().position(|&i| i == index)?;
+        let last_focused = self
+            .focused_last
+            .and_then(|l| tiled.iter().position(|&i| i == l));
+        let count = tiled.len();
+        let masters = self.settings.num_master.min(count);
+        let in_master = position < masters;
+        let (first, last) = if in_master {
+            (0, masters - 1)
+        } else {
+            (masters, count - 1)
+        };
+
+        let target = match direction {
+            Direction::Up if position > first => position - 1,
+            Direction::Down if position < last => position + 1,
+            Direction::Left if !in_master && masters > 0 => {
+                last_focused.filter(|&p| p < masters).unwrap_or(0)
+            }
+            Direction::Right if in_master && count > masters => {
+                last_focused.filter(|&p| p >= masters).unwrap_or(masters)
+            }
+            _ => return None,
+        };
+        Some(tiled[target])
+    }
+
+    /// Add delta windows to the master column, never going below none or
+    /// past every tiled window being a master
+    fn change_master_count(&mut self, delta: isize) {
+        let tiled = self
+            .windows
+            .iter()
+            .filter(|c| !c.floating && !c.fullscreen)
+            .count();
+        let count = self.settings.num_master as isize + delta;
+        self.settings.num_master = count.clamp(0, tiled.max(1) as isize) as usize;
+        self.apply_layout();
+    }
+
+    fn change_inner_gap(&mut self, delta: i32) {
+        let gaps = &mut self.settings.gaps;
+        gaps.inner = rect::grow(gaps.inner, delta);
+        gaps.enabled = true;
+        self.apply_layout();
+    }
+
+    /// Change the gap at every edge of the screen by delta
+    fn change_outer_gap(&mut self, delta: i32) {
+        let gaps = &mut self.settings.gaps;
+        gaps.outer.grow(delta);
+        gaps.enabled = true;
+        self.apply_layout();
+    }
+
+    fn toggle_gaps(&mut self) {
+        self.settings.gaps.enabled = !self.settings.gaps.enabled;
+        self.apply_layout();
+    }
+
+    /// Grow the master column by delta as a fraction of the screen width
+    fn change_master_factor(&mut self, delta: f32) {
+        let factor = self.settings.master_fact + delta;
+        self.settings.master_fact = factor.clamp(MIN_MASTER_FACT, MAX_MASTER_FACT);
+        self.apply_layout();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::{self, FakeBackend, FakeWindow, Request};
+
+    fn desktops(count: usize, layout: Layout) -> (Rc<FakeBackend>, Desktops) {
+        desktops_on(count, layout, &[Rect::new(0, 0, 1000, 800)])
+    }
+
+    fn desktops_on(count: usize, layout: Layout, screens: &[Rect]) -> (Rc<FakeBackend>, Desktops) {
+        let fake = FakeBackend::new();
+        let backend: Rc<dyn Backend> = fake.clone();
+        let desktops = (0..count)
+            .map(|i| {
+                Desktop::new(
+                    &i.to_string(),
+                    layout,
+                    TileSettings::default(),
+                    BorderConfig::default(),
+                    backend.clone(),
+                )
+            })
+            .collect();
+        (fake, Desktops::new(desktops, screens))
+    }
+
+    fn client(fake: &FakeBackend, window: x::WindowId) -> Client {
+        let window = fake.create_window(window, FakeWindow::default());
+        Client::new(fake, window, BorderConfig::default().title_height)
+    }
+
+    #[test]
+    fn added_windows_are_tiled_and_first_is_focused() {
+        let (fake, mut desktops) = desktops(2, Layout::Tile);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+
+        assert!(fake.is_mapped(1) && fake.is_mapped(2));
+        assert_eq!(fake.focused(), Some(1));
+        assert_eq!(
+            desktops.locate(&desktops.desktops[0].windows[1].window),
+            Some(ClientLocation {
+                desktop: 0,
+                index: 1
+            })
+        );
+    }
+
+    #[test]
+    fn switching_desktops_hides_and_shows_windows() {
+        let (fake, mut desktops) = desktops(2, Layout::Tile);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(1, client(&fake, 2));
+        assert!(!fake.is_mapped(2));
+
+        desktops.focus_desktop(1);
+        assert!(!fake.is_mapped(1));
+        assert!(fake.is_mapped(2));
+        assert_eq!(fake.focused(), Some(2));
+
+        desktops.focus_desktop(0);
+        assert!(fake.is_mapped(1));
+        assert!(!fake.is_mapped(2));
+        assert_eq!(fake.focused(), Some(1));
+    }
+
+    #[test]
+    fn moved_window_follows_registry() {
+        let (fake, mut desktops) = desktops(2, Layout::Tile);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+        let moved = desktops.desktops[0].windows[0].window;
+
+        desktops.move_focused_to_desktop(1, false);
+        assert!(!fake.is_mapped(1));
+        assert_eq!(fake.focused(), Some(2));
+        assert_eq!(
+            desktops.locate(&moved),
+            Some(ClientLocation {
+                desktop: 1,
+                index: 0
+            })
+        );
+
+        desktops.remove_window(&moved);
+        assert!(!desktops.contains(&moved));
+        assert_eq!(desktops.desktops[1].focused_window, None);
+    }
+
+    #[test]
+    fn removing_other_window_keeps_focus() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        for id in 1..=3 {
+            desktops.add_window_to(desktops.focused_desktop, client(&fake, id));
+        }
+        desktops.desktops[0].focused_window = Some(2);
+
+        let first = desktops.desktops[0].windows[0].window;
+        desktops.remove_window(&first);
+        assert_eq!(desktops.desktops[0].focused_window, Some(1));
+        assert_eq!(
+            desktops.desktops[0].get_focused_window().map(|w| w.window),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn focus_and_move_respect_master_count() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        for id in 1..=4 {
+            desktops.add_window_to(desktops.focused_desktop, client(&fake, id));
         }
-        if self.focused_last.is_none() {
-            self.focused_last = Some(1);
+        desktops.change_master_count(1);
+        let ids = |d: &Desktops| -> Vec<x::WindowId> {
+            d.desktops[0]
+                .windows
+                .iter()
+                .map(|c| c.window.window)
+                .collect()
+        };
+
+        desktops.focus_window(&Direction::Down);
+        assert_eq!(fake.focused(), Some(2));
+        desktops.focus_window(&Direction::Down);
+        assert_eq!(fake.focused(), Some(2));
+        desktops.focus_window(&Direction::Right);
+        assert_eq!(fake.focused(), Some(3));
+        desktops.focus_window(&Direction::Down);
+        desktops.focus_window(&Direction::Left);
+        assert_eq!(fake.focused(), Some(1));
+
+        desktops.move_window_tile(&Direction::Right);
+        assert_eq!(ids(&desktops), vec![4, 2, 3, 1]);
+        assert_eq!(desktops.desktops[0].focused_window, Some(3));
+
+        desktops.change_master_factor(1.0);
+        assert_eq!(desktops.desktops[0].settings.master_fact, MAX_MASTER_FACT);
+
+        // Masters stop at the number of windows, so going back is immediate
+        for _ in 0..5 {
+            desktops.change_master_count(1);
         }
+        assert_eq!(desktops.desktops[0].settings.num_master, 4);
+        desktops.change_master_count(-1);
+        assert_eq!(desktops.desktops[0].settings.num_master, 3);
+    }
 
-        let focused_window = self.focused_window.unwrap();
+    #[test]
+    fn tiled_neighbours_skip_floating_windows() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        for id in 1..=3 {
+            desktops.add_window_to(0, client(&fake, id));
+        }
+        // The first window floats, leaving one tiled window per column
+        desktops.toggle_floating();
+        desktops.desktops[0].focus_index(1);
+        assert_eq!(fake.focused(), Some(2));
 
-        match direction {
-            Direction::Up => {
-                if focused_window == 0 || focused_window == 1 {
-                    return;
-                }
+        desktops.focus_window(&Direction::Up);
+        assert_eq!(fake.focused(), Some(2));
+        desktops.focus_window(&Direction::Right);
+        assert_eq!(fake.focused(), Some(3));
+        desktops.focus_window(&Direction::Down);
+        assert_eq!(fake.focused(), Some(3));
+        desktops.focus_window(&Direction::Left);
+        assert_eq!(fake.focused(), Some(2));
 
-                self.windows.swap(focused_window, focused_window - 1);
-                match self.focused_window.as_mut() {
-                    Some(i) => *i -= 1,
-                    None => {}
-                };
-                self.apply_layout();
-            },
-            Direction::Down => {
-                if focused_window == 0 || focused_window == self.windows.len() - 1 {
-                    return;
-                }
+        desktops.move_window_tile(&Direction::Right);
+        let ids: Vec<x::WindowId> = desktops.desktops[0]
+            .windows
+            .iter()
+            .map(|c| c.window.window)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 2]);
+        assert_eq!(desktops.desktops[0].focused_window, Some(2));
+        assert_eq!(desktops.desktops[0].windows[1].geometry().x, 0);
+        assert_eq!(desktops.desktops[0].windows[2].geometry().x, 505);
+    }
 
-                self.windows.swap(focused_window, focused_window + 1);
-                match self.focused_window.as_mut() {
-                    Some(i) => *i += 1,
-                    None => {}
-                };
-                self.apply_layout();
-            },
-            Direction::Left => {
-                if focused_window == 0 {
-                    return;
-                }
-                self.windows.swap(focused_window, 0);
-                match self.focused_last.as_mut() {
-                    Some(i) => *i = focused_window,
-                    None => {},
-                };
-                match self.focused_window.as_mut() {
-                    Some(i) => *i = 0,
-                    None => {}
-                };
-                self.apply_layout();
-            },
-            Direction::Right => {
-                if focused_window != 0 {
-                    return;
-                }
-                self.windows.swap(focused_window, self.focused_last.unwrap());
-                match self.focused_window.as_mut() {
-                    Some(i) => *i = self.focused_last.unwrap(),
-                    None => {}
-                };
-                match self.focused_last.as_mut() {
-                    Some(i) => *i = 0,
-                    None => {},
-                };
-                self.apply_layout();
-            },
+    #[test]
+    fn monocle_maps_only_focused_window() {
+        let (fake, mut desktops) = desktops(1, Layout::Monocle);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+        assert!(fake.is_mapped(1));
+        assert!(!fake.is_mapped(2));
+
+        fake.take_requests();
+        let border = BorderConfig::default();
+        desktops.focus_window(&Direction::Right);
+        assert_eq!(
+            fake.take_requests(),
+            vec![
+                Request::Unmap(fake::frame(1)),
+                Request::Map(fake::frame(2)),
+                Request::Focus(2),
+                Request::BorderColor(fake::frame(1), border.unfocused),
+                Request::DrawTitle(fake::frame(1), String::new()),
+                Request::BorderColor(fake::frame(2), border.focused),
+                Request::DrawTitle(fake::frame(2), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn switching_to_monocle_hides_other_windows() {
+        let (fake, mut desktops) = desktops(2, Layout::Tile);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+
+        desktops.change_layout(&Layout::Monocle);
+        assert!(fake.is_mapped(1));
+        assert!(!fake.is_mapped(2));
+
+        desktops.focus_desktop(1);
+        assert!(!fake.is_mapped(1));
+        assert!(!fake.is_mapped(2));
+    }
+
+    #[test]
+    fn borders_show_focus_across_monitors() {
+        let left = Rect::new(0, 0, 1000, 800);
+        let right = Rect::new(1000, 0, 1000, 800);
+        let (fake, mut desktops) = desktops_on(2, Layout::Tile, &[left, right]);
+        let border = BorderConfig::default();
+        desktops.add_window_to(0, client(&fake, 1));
+        desktops.add_window_to(1, client(&fake, 2));
+        let urgent = FakeWindow {
+            urgent: true,
+            ..Default::default()
+        };
+        let window = fake.create_window(3, urgent);
+        desktops.add_window_to(0, Client::new(&*fake, window, border.title_height));
+        assert_eq!(fake.border_color(fake::frame(1)), Some(border.focused));
+        assert_eq!(fake.border_color(fake::frame(2)), Some(border.unfocused));
+        assert_eq!(fake.border_color(fake::frame(3)), Some(border.urgent));
+        assert_eq!(fake.focused(), Some(1));
+
+        // Only the window focused on the focused monitor looks focused
+        desktops.focus_monitor(1);
+        assert_eq!(fake.border_color(fake::frame(1)), Some(border.unfocused));
+        assert_eq!(fake.border_color(fake::frame(2)), Some(border.focused));
+        assert_eq!(fake.focused(), Some(2));
+
+        desktops.focus_monitor(1);
+        desktops.focus_window(&Direction::Right);
+        assert_eq!(fake.border_color(fake::frame(1)), Some(border.unfocused));
+        assert_eq!(fake.border_color(fake::frame(2)), Some(border.unfocused));
+        assert_eq!(fake.border_color(fake::frame(3)), Some(border.focused));
+    }
+
+    #[test]
+    fn windows_are_framed_below_their_title() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        let term = FakeWindow {
+            title: "term".to_string(),
+            ..Default::default()
+        };
+        let window = fake.create_window(1, term);
+        let border = BorderConfig::default();
+        fake.take_requests();
+        let client = Client::new(&*fake, window, border.title_height);
+        desktops.add_window_to(0, client);
+        assert_eq!(
+            fake.take_requests(),
+            vec![
+                Request::CreateFrame(1),
+                Request::BorderWidth(fake::frame(1), 1),
+                Request::FrameExtents(1, 1, 18),
+                Request::Map(fake::frame(1)),
+                Request::Configure(fake::frame(1), Rect::new(0, 0, 998, 798)),
+                Request::Configure(1, Rect::new(0, 18, 998, 780)),
+                Request::BorderColor(fake::frame(1), border.focused),
+                Request::DrawTitle(fake::frame(1), "term".to_string()),
+                Request::Focus(1),
+                Request::WindowDesktop(1, 0),
+                Request::ClientList(vec![1]),
+            ]
+        );
+
+        // The window goes back to the root when it is unmanaged
+        desktops.remove_window(&window);
+        assert_eq!(
+            fake.take_requests(),
+            vec![Request::DestroyFrame(1), Request::ClientList(vec![])]
+        );
+    }
+
+    #[test]
+    fn monitors_show_their_own_desktops() {
+        let left = Rect::new(0, 0, 1000, 800);
+        let right = Rect::new(1000, 0, 1000, 800);
+        let (fake, mut desktops) = desktops_on(3, Layout::Monocle, &[left, right]);
+        desktops.add_window_to(0, client(&fake, 1));
+        desktops.add_window_to(1, client(&fake, 2));
+        assert!(fake.is_mapped(1) && fake.is_mapped(2));
+        assert_eq!(fake.focused(), Some(1));
+
+        desktops.focus_monitor(1);
+        assert_eq!(desktops.focused_desktop, 1);
+        assert_eq!(fake.focused(), Some(2));
+
+        // Wraps around to the left monitor
+        desktops.move_focused_to_monitor(1);
+        let moved = &desktops.desktops[0].windows[1];
+        assert_eq!(moved.geometry().x, 0);
+        assert!(desktops.desktops[1].windows.is_empty());
+
+        desktops.focus_desktop(0);
+        assert_eq!(desktops.focused_monitor, 0);
+        desktops.focus_desktop(2);
+        assert!(!fake.is_mapped(1) && !fake.is_mapped(2));
+        assert_eq!(desktops.monitors[0].desktop, 2);
+        assert_eq!(desktops.desktops[2].screen, left);
+    }
+
+    #[test]
+    fn vanished_monitor_keeps_focused_desktop_in_view() {
+        let left = Rect::new(0, 0, 1000, 800);
+        let right = Rect::new(1000, 0, 1000, 800);
+        let (fake, mut desktops) = desktops_on(3, Layout::Float, &[left, right]);
+        desktops.add_window_to(0, client(&fake, 1));
+        desktops.add_window_to(1, client(&fake, 2));
+        desktops.focus_monitor(1);
+
+        let small = Rect::new(0, 0, 50, 50);
+        desktops.set_monitors(&[small]);
+        assert_eq!(desktops.monitors.len(), 1);
+        assert_eq!(desktops.focused_desktop, 1);
+        assert!(fake.is_mapped(1) && fake.is_mapped(2));
+        assert_eq!(
+            desktops.desktops[1].windows[0].geometry(),
+            Rect::new(0, 0, 48, 48)
+        );
+
+        desktops.set_monitors(&[small, right]);
+        assert_eq!(desktops.monitors[1].desktop, 0);
+        assert!(fake.is_mapped(1) && fake.is_mapped(2));
+        assert_eq!(fake.focused(), Some(1));
+    }
+
+    #[test]
+    fn unplugged_monitor_hands_its_windows_over() {
+        let left = Rect::new(0, 0, 1000, 800);
+        let right = Rect::new(1000, 0, 1000, 800);
+        let (fake, mut desktops) = desktops_on(3, Layout::Tile, &[left, right]);
+        desktops.add_window_to(0, client(&fake, 1));
+        desktops.add_window_to(1, client(&fake, 2));
+        let mut floating = client(&fake, 3);
+        floating.floating = true;
+        floating.frame.set_rect(Rect::new(1500, 100, 100, 118));
+        let window = floating.window;
+        desktops.add_window_to(1, floating);
+
+        desktops.set_monitors(&[left]);
+        assert_eq!(desktops.focused_desktop, 0);
+        assert!(!desktops.desktops[1].active);
+        let ids: Vec<x::WindowId> = desktops
+            .managed_windows()
+            .iter()
+            .map(|w| w.window)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert!((1..=3).all(|id| fake.is_mapped(id)));
+        assert_eq!(desktops.locate(&window).map(|l| l.desktop), Some(0));
+        assert_eq!(
+            desktops.desktops[0].windows[1].geometry(),
+            Rect::new(505, 0, 493, 798)
+        );
+        assert_eq!(
+            desktops.desktops[0].windows[2].geometry(),
+            Rect::new(898, 100, 100, 118)
+        );
+    }
+
+    #[test]
+    fn raised_windows_stack_last() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        for id in 1..=3 {
+            desktops.add_window_to(0, client(&fake, id));
+        }
+        assert_eq!(fake.stacking(), vec![1, 2, 3]);
+
+        fake.take_requests();
+        desktops.toggle_floating();
+        assert_eq!(fake.stacking(), vec![2, 3, 1]);
+        assert!(fake
+            .take_requests()
+            .contains(&Request::Raise(fake::frame(1))));
+
+        let second = desktops.desktops[0].windows[1].window;
+        desktops.set_fullscreen(&second, Some(true));
+        assert_eq!(fake.stacking(), vec![3, 1, 2]);
+        let raised: Vec<Request> = fake
+            .take_requests()
+            .into_iter()
+            .filter(|r| matches!(r, Request::Raise(..)))
+            .collect();
+        assert_eq!(raised, vec![Request::Raise(fake::frame(2))]);
+
+        desktops.change_layout(&Layout::Float);
+        assert_eq!(fake.stacking(), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn desktop_state_is_published() {
+        let (fake, mut desktops) = desktops(2, Layout::Tile);
+        assert_eq!(
+            fake.take_requests(),
+            vec![
+                Request::DesktopNames(vec![String::from("0"), String::from("1")]),
+                Request::CurrentDesktop(0),
+            ]
+        );
+        let published = |fake: &FakeBackend| -> Vec<Request> {
+            fake.take_requests()
+                .into_iter()
+                .filter(|r| {
+                    matches!(
+                        r,
+                        Request::ClientList(..)
+                            | Request::CurrentDesktop(..)
+                            | Request::WindowDesktop(..)
+                    )
+                })
+                .collect()
+        };
+
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+        fake.take_requests();
+        desktops.move_focused_to_desktop(1, true);
+        assert_eq!(
+            published(&fake),
+            vec![Request::WindowDesktop(1, 1), Request::CurrentDesktop(1)]
+        );
+
+        let moved = desktops.desktops[1].windows[0].window;
+        desktops.remove_window(&moved);
+        assert_eq!(published(&fake), vec![Request::ClientList(vec![2])]);
+    }
+
+    #[test]
+    fn pager_requests_act_on_any_window() {
+        let (fake, mut desktops) = desktops(2, Layout::Monocle);
+        for id in 1..=3 {
+            desktops.add_window_to(desktops.focused_desktop, client(&fake, id));
         }
+        let third = desktops.desktops[0].windows[2].window;
+        desktops.move_window_to_desktop(&third, 1, false);
+        assert_eq!(fake.focused(), Some(1));
+        assert!(!fake.is_mapped(3));
+
+        desktops.activate_window(&third);
+        assert_eq!(desktops.focused_desktop, 1);
+        assert_eq!(fake.focused(), Some(3));
+
+        let second = desktops.desktops[0].windows[1].window;
+        desktops.activate_window(&second);
+        assert!(fake.is_mapped(2) && !fake.is_mapped(1));
+        assert_eq!(fake.focused(), Some(2));
+
+        fake.take_requests();
+        desktops.close_window(&second);
+        assert_eq!(fake.take_requests(), vec![Request::Delete(2)]);
+    }
+
+    #[test]
+    fn fullscreen_covers_screen_and_restores_tile() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+        let tiled = desktops.desktops[0].windows[0].geometry();
+
+        desktops.toggle_fullscreen();
+        let windows = &desktops.desktops[0].windows;
+        assert_eq!(windows[0].geometry(), Rect::new(0, 0, 1000, 800));
+        assert_eq!(windows[0].window.rect(), Rect::new(0, 0, 1000, 800));
+        assert_eq!(windows[0].border_width, 0);
+        assert_eq!(windows[1].geometry(), Rect::new(0, 0, 998, 798));
+        let requests = fake.take_requests();
+        assert!(requests.contains(&Request::FullscreenState(1, true)));
+        assert!(requests.contains(&Request::Raise(fake::frame(1))));
+
+        let window = desktops.desktops[0].windows[0].window;
+        desktops.set_fullscreen(&window, Some(false));
+        assert_eq!(desktops.desktops[0].windows[0].geometry(), tiled);
+        assert_eq!(
+            desktops.desktops[0].windows[0].title_height,
+            BorderConfig::default().title_height
+        );
+    }
+
+    #[test]
+    fn floating_window_leaves_tiling_and_stays_above() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 1));
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+        let tiled = desktops.desktops[0].windows[0].geometry();
+        fake.take_requests();
+
+        desktops.toggle_floating();
+        let windows = &desktops.desktops[0].windows;
+        assert!(windows[0].floating);
+        assert_eq!(windows[0].geometry(), tiled);
+        assert_eq!(windows[1].geometry(), Rect::new(0, 0, 998, 798));
+        assert!(fake
+            .take_requests()
+            .contains(&Request::Raise(fake::frame(1))));
+
+        // A floating window is free to move, and does not get tiled again
+        let window = desktops.desktops[0].windows[0].window;
+        assert!(!desktops.enforce_layout(&window));
+        desktops.apply_layouts();
+        assert_eq!(desktops.desktops[0].windows[0].geometry(), tiled);
+
+        desktops.toggle_floating();
+        assert!(!desktops.desktops[0].windows[0].floating);
+        assert_eq!(desktops.desktops[0].windows[0].geometry(), tiled);
+    }
+
+    #[test]
+    fn dragged_windows_float_within_their_size_hints() {
+        let (fake, mut desktops) = desktops(1, Layout::Tile);
+        let hints = FakeWindow {
+            size_hints: x::SizeHints {
+                min: Some((200, 150)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let window = fake.create_window(1, hints);
+        let title_height = BorderConfig::default().title_height;
+        desktops.add_window_to(
+            desktops.focused_desktop,
+            Client::new(&*fake, window, title_height),
+        );
+        desktops.add_window_to(desktops.focused_desktop, client(&fake, 2));
+        let frame = desktops.desktops[0].windows[0].frame;
+        let tiled = desktops.desktops[0].windows[0].geometry();
+
+        assert_eq!(desktops.start_drag(&frame), Some(tiled));
+        assert!(desktops.desktops[0].windows[0].floating);
+        assert_eq!(
+            desktops.desktops[0].windows[1].geometry(),
+            Rect::new(0, 0, 998, 798)
+        );
+
+        desktops.drag_window(&frame, Rect::new(50, 60, 10, 10));
+        // The frame leaves room for the title bar above the minimum size
+        assert_eq!(
+            desktops.desktops[0].windows[0].geometry(),
+            Rect::new(50, 60, 200, 150 + title_height)
+        );
     }
 }
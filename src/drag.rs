@@ -0,0 +1,80 @@
+use crate::rect::{self, Rect};
+use crate::x;
+
+/// What dragging a window with a button held down does to it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DragKind {
+    Move,
+    Resize,
+}
+
+impl DragKind {
+    /// Button 1 moves windows and button 3 resizes them
+    pub fn for_button(button: u8) -> Option<DragKind> {
+        match button {
+            1 => Some(DragKind::Move),
+            3 => Some(DragKind::Resize),
+            _ => None,
+        }
+    }
+}
+
+/// A window being dragged by its frame, remembering where the pointer and
+/// the frame were when the button went down
+#[derive(Debug)]
+pub struct Drag {
+    pub frame: x::Window,
+    pub kind: DragKind,
+    start: (i32, i32),
+    geometry: Rect,
+}
+
+impl Drag {
+    pub fn new(frame: x::Window, kind: DragKind, start: (i32, i32), geometry: Rect) -> Drag {
+        Drag {
+            frame,
+            kind,
+            start,
+            geometry,
+        }
+    }
+
+    /// Geometry of the frame with the pointer at position on the root.
+    /// Resizing moves the bottom right corner
+    pub fn geometry_at(&self, position: (i32, i32)) -> Rect {
+        let dx = position.0.saturating_sub(self.start.0);
+        let dy = position.1.saturating_sub(self.start.1);
+        let geometry = self.geometry;
+        match self.kind {
+            DragKind::Move => Rect {
+                x: geometry.x.saturating_add(dx),
+                y: geometry.y.saturating_add(dy),
+                ..geometry
+            },
+            DragKind::Resize => Rect {
+                width: rect::grow(geometry.width, dx),
+                height: rect::grow(geometry.height, dy),
+                ..geometry
+            }
+            .at_least_min_size(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drags_follow_the_pointer() {
+        let geometry = Rect::new(100, 100, 300, 200);
+        let frame = x::Window::default();
+
+        let drag = Drag::new(frame, DragKind::Move, (150, 120), geometry);
+        assert_eq!(drag.geometry_at((100, 300)), Rect::new(50, 280, 300, 200));
+
+        let drag = Drag::new(frame, DragKind::Resize, (400, 300), geometry);
+        assert_eq!(drag.geometry_at((450, 250)), Rect::new(100, 100, 350, 150));
+        assert_eq!(drag.geometry_at((0, 0)), Rect::new(100, 100, 1, 1));
+    }
+}
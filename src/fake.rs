@@ -0,0 +1,377 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+
+use crate::backend::Backend;
+use crate::config::Color;
+use crate::key::{KeyMap, ModMask};
+use crate::rect::Rect;
+use crate::x::{Cursor, RelayoutFilter, SizeHints, Window, WindowChanges, WindowId, XEvent};
+
+/// A request made through the fake backend
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Map(WindowId),
+    Unmap(WindowId),
+    Configure(WindowId, Rect),
+    BorderWidth(WindowId, u32),
+    BorderColor(WindowId, Color),
+    Focus(WindowId),
+    Delete(WindowId),
+    GrabKeys(WindowId),
+    UngrabKeys(WindowId),
+    CreateFrame(WindowId),
+    DestroyFrame(WindowId),
+    FrameExtents(WindowId, u32, u32),
+    DrawTitle(WindowId, String),
+    ClientList(Vec<WindowId>),
+    DesktopNames(Vec<String>),
+    CurrentDesktop(usize),
+    WindowDesktop(WindowId, usize),
+    Raise(WindowId),
+    FullscreenState(WindowId, bool),
+    GrabButtons(WindowId, ModMask, bool),
+    GrabPointer(Cursor),
+    UngrabPointer,
+    ReplayPointer,
+    Release,
+}
+
+/// Id of the fake root window
+pub const ROOT: WindowId = 999;
+
+/// Id the fake gives the frame of a window
+pub fn frame(window: WindowId) -> WindowId {
+    window + 1000
+}
+
+/// Properties a client would have set on its window
+#[derive(Clone, Debug, Default)]
+pub struct FakeWindow {
+    pub instance: String,
+    pub class: String,
+    pub title: String,
+    pub size_hints: SizeHints,
+    pub urgent: bool,
+    pub fullscreen: bool,
+    pub transient_for: Option<WindowId>,
+}
+
+/// In-memory stand-in for the X server that records every request made of
+/// it. Event mask changes are not recorded
+#[derive(Default)]
+pub struct FakeBackend {
+    requests: RefCell<Vec<Request>>,
+    windows: RefCell<HashMap<WindowId, FakeWindow>>,
+    mapped: RefCell<HashSet<WindowId>>,
+    /// Frame of each reparented window
+    frames: RefCell<HashMap<WindowId, WindowId>>,
+    focused: Cell<Option<WindowId>>,
+    border_colors: RefCell<HashMap<WindowId, Color>>,
+    /// Last published stacking order, bottom first
+    stacking: RefCell<Vec<WindowId>>,
+    /// Events handed out by wait_for_event, oldest first
+    events: RefCell<VecDeque<(u16, XEvent)>>,
+    /// Sequence number of the last request made
+    sequence: Cell<u16>,
+    relayout: RelayoutFilter,
+    /// Monitors reported, a single 1000x800 screen when empty
+    monitors: RefCell<Vec<Rect>>,
+    saved_state: RefCell<Option<String>>,
+}
+
+impl FakeBackend {
+    pub fn new() -> Rc<FakeBackend> {
+        Rc::new(FakeBackend::default())
+    }
+
+    /// Create an unmapped window with the given properties
+    pub fn create_window(&self, window: WindowId, properties: FakeWindow) -> Window {
+        self.windows.borrow_mut().insert(window, properties);
+        Window {
+            window,
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        }
+    }
+
+    /// Requests made since the last call, oldest first
+    pub fn take_requests(&self) -> Vec<Request> {
+        self.requests.borrow_mut().drain(..).collect()
+    }
+
+    /// Whether the window is mapped, along with its frame if it has one
+    pub fn is_mapped(&self, window: WindowId) -> bool {
+        let mapped = self.mapped.borrow();
+        let frame_mapped = match self.frames.borrow().get(&window) {
+            Some(frame) => mapped.contains(frame),
+            None => true,
+        };
+        mapped.contains(&window) && frame_mapped
+    }
+
+    pub fn focused(&self) -> Option<WindowId> {
+        self.focused.get()
+    }
+
+    pub fn border_color(&self, window: WindowId) -> Option<Color> {
+        self.border_colors.borrow().get(&window).copied()
+    }
+
+    pub fn stacking(&self) -> Vec<WindowId> {
+        self.stacking.borrow().clone()
+    }
+
+    /// Queue an event for wait_for_event to return, as if the server sent it
+    /// after handling every request made so far
+    pub fn send_event(&self, event: XEvent) {
+        let sequence = self.sequence.get();
+        self.events.borrow_mut().push_back((sequence, event));
+    }
+
+    fn record(&self, request: Request) {
+        self.sequence.set(self.sequence.get().wrapping_add(1));
+        self.requests.borrow_mut().push(request);
+    }
+
+    /// Record a request that may move or map a window under the pointer
+    fn record_relayout(&self, request: Request) {
+        self.record(request);
+        self.relayout.note(self.sequence.get());
+    }
+
+    fn properties(&self, window: &Window) -> FakeWindow {
+        self.windows
+            .borrow()
+            .get(&window.window)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl Backend for FakeBackend {
+    fn setup(&self, keys: &KeyMap) {
+        self.grab_keys(&self.root_window(), keys);
+    }
+
+    fn get_existing_windows(&self) -> Vec<(Window, bool)> {
+        Vec::new()
+    }
+
+    fn get_monitors(&self) -> Vec<Rect> {
+        let monitors = self.monitors.borrow();
+        if monitors.is_empty() {
+            return vec![Rect::new(0, 0, 1000, 800)];
+        }
+        monitors.clone()
+    }
+
+    fn release(&self) {
+        self.record(Request::Release);
+    }
+
+    fn set_saved_state(&self, state: &str) {
+        *self.saved_state.borrow_mut() = Some(state.to_string());
+    }
+
+    fn take_saved_state(&self) -> Option<String> {
+        self.saved_state.borrow_mut().take()
+    }
+
+    fn root_window(&self) -> Window {
+        Window {
+            window: ROOT,
+            x: 0,
+            y: 0,
+            width: 1000,
+            height: 800,
+        }
+    }
+
+    fn wait_for_event(&self, _wakeup: RawFd) -> Option<XEvent> {
+        // Crossings caused by moving windows are dropped, as X does
+        loop {
+            let (sequence, event) = self.events.borrow_mut().pop_front()?;
+            let moved_by_wm = self.relayout.caused_by_relayout(sequence);
+            match event {
+                XEvent::EnterNotify(_) if moved_by_wm => continue,
+                event => return Some(event),
+            }
+        }
+    }
+
+    fn register_window(&self, _window: &Window) {}
+
+    fn grab_buttons(&self, window: &Window, modifier: ModMask, click_to_focus: bool) {
+        self.record(Request::GrabButtons(
+            window.window,
+            modifier,
+            click_to_focus,
+        ));
+    }
+
+    fn grab_pointer(&self, cursor: Cursor) -> bool {
+        self.record(Request::GrabPointer(cursor));
+        true
+    }
+
+    fn ungrab_pointer(&self) {
+        self.record(Request::UngrabPointer);
+    }
+
+    fn replay_pointer(&self) {
+        self.record(Request::ReplayPointer);
+    }
+
+    fn flush(&self) {
+        // The fence is a request of its own, but not one worth recording
+        if self.relayout.needs_fence() {
+            self.sequence.set(self.sequence.get().wrapping_add(1));
+            self.relayout.fence(self.sequence.get());
+        }
+    }
+
+    fn map_window(&self, window: &Window) {
+        self.mapped.borrow_mut().insert(window.window);
+        self.record_relayout(Request::Map(window.window));
+    }
+
+    fn unmap_window(&self, window: &Window) {
+        self.mapped.borrow_mut().remove(&window.window);
+        self.record_relayout(Request::Unmap(window.window));
+    }
+
+    fn configure_window(&self, window: &Window, window_changes: &WindowChanges) {
+        let geometry = Rect::new(
+            window_changes.x,
+            window_changes.y,
+            window_changes.width,
+            window_changes.height,
+        );
+        self.record_relayout(Request::Configure(window.window, geometry));
+    }
+
+    fn create_frame(&self, window: &Window, title_height: u32) -> Window {
+        let id = frame(window.window);
+        self.frames.borrow_mut().insert(window.window, id);
+        self.mapped.borrow_mut().insert(window.window);
+        self.record(Request::CreateFrame(window.window));
+        Window {
+            window: id,
+            height: window.height + title_height,
+            ..*window
+        }
+    }
+
+    fn destroy_frame(&self, frame: &Window, window: &Window) {
+        self.frames.borrow_mut().remove(&window.window);
+        self.mapped.borrow_mut().remove(&frame.window);
+        self.record_relayout(Request::DestroyFrame(window.window));
+    }
+
+    fn set_frame_extents(&self, window: &Window, border_width: u32, title_height: u32) {
+        self.record(Request::FrameExtents(
+            window.window,
+            border_width,
+            title_height,
+        ));
+    }
+
+    fn draw_title(
+        &self,
+        frame: &Window,
+        title: &str,
+        _height: u32,
+        _foreground: Color,
+        _background: Color,
+    ) {
+        self.record(Request::DrawTitle(frame.window, title.to_string()));
+    }
+
+    fn set_client_list(&self, windows: &[WindowId], stacking: &[WindowId]) {
+        *self.stacking.borrow_mut() = stacking.to_vec();
+        self.record(Request::ClientList(windows.to_vec()));
+    }
+
+    fn set_desktop_names(&self, names: &[String]) {
+        self.record(Request::DesktopNames(names.to_vec()));
+    }
+
+    fn set_current_desktop(&self, desktop: usize) {
+        self.record(Request::CurrentDesktop(desktop));
+    }
+
+    fn set_window_desktop(&self, window: &Window, desktop: usize) {
+        self.record(Request::WindowDesktop(window.window, desktop));
+    }
+
+    fn set_border_width(&self, window: &Window, width: u32) {
+        self.record_relayout(Request::BorderWidth(window.window, width));
+    }
+
+    fn raise_window(&self, window: &Window) {
+        self.record_relayout(Request::Raise(window.window));
+    }
+
+    fn set_fullscreen_state(&self, window: &Window, fullscreen: bool) {
+        self.record(Request::FullscreenState(window.window, fullscreen));
+    }
+
+    fn set_border_color(&self, window: &Window, color: Color) {
+        self.border_colors.borrow_mut().insert(window.window, color);
+        self.record(Request::BorderColor(window.window, color));
+    }
+
+    fn is_viewable(&self, window: &Window) -> bool {
+        self.is_mapped(window.window)
+    }
+
+    fn focus_window(&self, window: Window) {
+        self.focused.set(Some(window.window));
+        self.record(Request::Focus(window.window));
+    }
+
+    fn delete_window(&self, window: &Window) {
+        self.record(Request::Delete(window.window));
+    }
+
+    fn grab_keys(&self, window: &Window, _keys: &KeyMap) {
+        self.record(Request::GrabKeys(window.window));
+    }
+
+    fn ungrab_keys(&self, window: &Window) {
+        self.record(Request::UngrabKeys(window.window));
+    }
+
+    fn track_window_events(&self, _window: &Window) {}
+
+    fn stop_window_events(&self, _window: &Window) {}
+
+    fn get_wm_class(&self, window: &Window) -> (String, String) {
+        let properties = self.properties(window);
+        (properties.instance, properties.class)
+    }
+
+    fn get_window_title(&self, window: &Window) -> String {
+        self.properties(window).title
+    }
+
+    fn get_size_hints(&self, window: &Window) -> SizeHints {
+        self.properties(window).size_hints
+    }
+
+    fn is_urgent(&self, window: &Window) -> bool {
+        self.properties(window).urgent
+    }
+
+    fn is_fullscreen(&self, window: &Window) -> bool {
+        self.properties(window).fullscreen
+    }
+
+    fn get_transient_for(&self, window: &Window) -> Option<WindowId> {
+        self.properties(window).transient_for
+    }
+}
@@ -1,8 +1,12 @@
 use std::collections::HashMap;
-use std::os::raw::c_uint;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_uint, c_ulong};
+use std::str::FromStr;
 
 use crate::command::Command;
 
+use x11::xlib;
 use xcb;
 
 pub type KeySymbol = c_uint;
@@ -19,7 +23,32 @@ pub enum Modifier {
     Mod5,
 }
 
+const MODIFIER_NAMES: [(Modifier, &str); 8] = [
+    (Modifier::Shift, "Shift"),
+    (Modifier::Lock, "Lock"),
+    (Modifier::Control, "Control"),
+    (Modifier::Mod1, "Mod1"),
+    (Modifier::Mod2, "Mod2"),
+    (Modifier::Mod3, "Mod3"),
+    (Modifier::Mod4, "Mod4"),
+    (Modifier::Mod5, "Mod5"),
+];
+
 impl Modifier {
+    pub fn from_name(name: &str) -> Option<Modifier> {
+        match name {
+            "Shift" => Some(Modifier::Shift),
+            "Lock" => Some(Modifier::Lock),
+            "Control" | "Ctrl" => Some(Modifier::Control),
+            "Mod1" | "Alt" => Some(Modifier::Mod1),
+            "Mod2" => Some(Modifier::Mod2),
+            "Mod3" => Some(Modifier::Mod3),
+            "Mod4" | "Super" => Some(Modifier::Mod4),
+            "Mod5" => Some(Modifier::Mod5),
+            _ => None,
+        }
+    }
+
     pub fn get_mod_mask(&self) -> ModMask {
         match self {
             Modifier::Shift => xcb::MOD_MASK_SHIFT,
@@ -52,7 +81,83 @@ impl Key {
 
         Key {
             modifier: mods,
-            key: key,
+            key,
+        }
+    }
+}
+
+/// Parses keys written as modifiers and a key name joined by '+',
+/// e.g. "Mod1+Shift+Return"
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Key, String> {
+        let mut parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        let name = match parts.pop() {
+            Some(n) if !n.is_empty() => n,
+            _ => return Err(format!("missing key name in \"{}\"", s)),
+        };
+
+        let mut modifier = 0;
+        for part in parts {
+            match Modifier::from_name(part) {
+                Some(m) => modifier |= m.get_mod_mask(),
+                None => return Err(format!("unknown modifier \"{}\"", part)),
+            }
+        }
+
+        match keysym_from_name(name) {
+            Some(key) => Ok(Key { modifier, key }),
+            None => Err(format!("unknown key \"{}\"", name)),
+        }
+    }
+}
+
+/// Look up a keysym by its X name, e.g. "Return" or "XF86AudioRaiseVolume".
+/// Raw keysyms can be given in hex, e.g. "0xff0d"
+pub fn keysym_from_name(name: &str) -> Option<KeySymbol> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // Latin-1 keysyms match their character, letters are bound lowercase
+        if c.is_ascii_alphanumeric() {
+            return Some(c.to_ascii_lowercase() as KeySymbol);
+        }
+    }
+
+    if let Some(hex) = name.strip_prefix("0x") {
+        return KeySymbol::from_str_radix(hex, 16).ok();
+    }
+
+    let name = CString::new(name).ok()?;
+    let key = unsafe { xlib::XStringToKeysym(name.as_ptr()) };
+    if key == 0 {
+        None
+    } else {
+        Some(key as KeySymbol)
+    }
+}
+
+/// The X name of a keysym, None if it has no name
+pub fn keysym_name(key: KeySymbol) -> Option<String> {
+    let name = unsafe { xlib::XKeysymToString(key as c_ulong) };
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Formats keys the same way they are parsed, modifiers first in X order
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES.iter() {
+            if self.modifier & modifier.get_mod_mask() != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match keysym_name(self.key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:#x}", self.key),
         }
     }
 }
@@ -60,3 +165,42 @@ impl Key {
 pub struct KeyMap {
     pub key_map: HashMap<Key, Command>,
 }
+
+impl KeyMap {
+    /// Every binding as (key, command) strings, sorted by key
+    pub fn describe(&self) -> Vec<(String, String)> {
+        let mut binds: Vec<(String, String)> = self
+            .key_map
+            .iter()
+            .map(|(key, command)| (key.to_string(), command.to_string()))
+            .collect();
+        binds.sort();
+        binds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_strings_round_trip() {
+        for s in &["Shift+Mod4+Return", "XF86AudioRaiseVolume", "Control+Mod1+a"] {
+            let key: Key = s.parse().unwrap();
+            assert_eq!(key.to_string(), *s);
+        }
+
+        let key: Key = "Mod4+Shift+Return".parse().unwrap();
+        assert_eq!(key.to_string(), "Shift+Mod4+Return");
+
+        let key: Key = "Alt+A".parse().unwrap();
+        assert_eq!(key, Key::new(&[Modifier::Mod1], x11::keysym::XK_a));
+    }
+
+    #[test]
+    fn invalid_key_strings() {
+        assert!("Hyper+a".parse::<Key>().is_err());
+        assert!("Mod1+NotAKey".parse::<Key>().is_err());
+        assert!("Mod1+".parse::<Key>().is_err());
+    }
+}
@@ -1,5 +1,10 @@
-use crate::x;
-use crate::Screen;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::backend::Backend;
+use crate::client::Client;
+use crate::config::BorderConfig;
+use crate::rect::{self, Rect};
 
 type Gap = u32;
 
@@ -10,114 +15,435 @@ pub enum Layout {
     Tile,
 }
 
-impl Layout {
-    pub fn apply(&self, conn: &x::Connection, windows: &Vec<x::Window>, screen: &Screen) {
-        match self {
-            Layout::Float => Layout::float(conn, windows),
-            Layout::Monocle => Layout::monocle(conn, windows, screen),
-            Layout::Tile => Layout::tile(conn, windows, screen),
-        };
+/// Parameters of the tiling layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileSettings {
+    pub gaps: Gaps,
+    pub num_master: usize,
+    pub master_fact: f32,
+    /// Shrink tiled windows to the nearest size their size hints allow
+    pub size_hints: bool,
+}
+
+/// Space left between windows and around the edge of the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gaps {
+    pub inner: Gap,
+    pub outer: Edges,
+    /// Leave out every gap while only one window is visible
+    pub smart: bool,
+    /// Gaps can be switched off without losing their sizes
+    pub enabled: bool,
+}
+
+/// A size for each edge of the screen
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Edges {
+    pub top: Gap,
+    pub right: Gap,
+    pub bottom: Gap,
+    pub left: Gap,
+}
+
+impl Default for TileSettings {
+    fn default() -> TileSettings {
+        TileSettings {
+            gaps: Gaps::default(),
+            num_master: 1,
+            master_fact: 0.5,
+            size_hints: true,
+        }
+    }
+}
+
+impl Default for Gaps {
+    fn default() -> Gaps {
+        Gaps {
+            inner: 10,
+            outer: Edges::default(),
+            smart: false,
+            enabled: true,
+        }
+    }
+}
+
+impl Gaps {
+    fn shown(&self, visible: usize) -> bool {
+        self.enabled && !(self.smart && visible <= 1)
     }
 
-    fn float(connection: &x::Connection, windows: &Vec<x::Window>) {
-        if windows.is_empty() {
-            return;
+    /// Gap between neighbouring windows when visible windows are shown
+    pub fn inner(&self, visible: usize) -> Gap {
+        if self.shown(visible) {
+            self.inner
+        } else {
+            0
         }
+    }
 
-        for window in windows {
-            connection.stop_window_events(&window);
-            connection.map_window(&window);
-            connection.track_window_events(&window);
+    /// The part of the screen left for windows inside the outer gaps
+    pub fn area(&self, screen: &Rect, visible: usize) -> Rect {
+        if !self.shown(visible) {
+            return *screen;
         }
+        let outer = &self.outer;
+        screen.inset(outer.top, outer.right, outer.bottom, outer.left)
     }
+}
 
-    fn monocle(connection: &x::Connection, windows: &Vec<x::Window>, screen: &Screen) {
-        if windows.is_empty() {
-            return;
+impl Edges {
+    pub fn all(gap: Gap) -> Edges {
+        Edges {
+            top: gap,
+            right: gap,
+            bottom: gap,
+            left: gap,
         }
+    }
 
-        let window_changes = x::WindowChanges {
-            x: screen.x,
-            y: screen.y,
-            width: screen.width,
-            height: screen.height,
-            border_width: 0,
-            sibling: 0,
-            stack_mode: 0,
+    /// Change every edge by delta, stopping at no gap
+    pub fn grow(&mut self, delta: i32) {
+        self.top = rect::grow(self.top, delta);
+        self.right = rect::grow(self.right, delta);
+        self.bottom = rect::grow(self.bottom, delta);
+        self.left = rect::grow(self.left, delta);
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Layout::Float => "float",
+            Layout::Monocle => "monocle",
+            Layout::Tile => "tile",
         };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
 
-        for window in windows {
-            connection.stop_window_events(&window);
-            //connection.map_window(&window);
-            connection.configure_window(&window, &window_changes);
-            connection.track_window_events(&window);
+    fn from_str(s: &str) -> Result<Layout, String> {
+        match s {
+            "float" => Ok(Layout::Float),
+            "monocle" => Ok(Layout::Monocle),
+            "tile" => Ok(Layout::Tile),
+            _ => Err(format!("unknown layout \"{}\"", s)),
         }
     }
+}
 
-    // TODO: Cleanup
-    fn tile(connection: &x::Connection, windows: &Vec<x::Window>, screen: &Screen) {
-        if windows.is_empty() {
-            return;
+impl Layout {
+    /// How high apply stacks a client: tiled windows at the bottom, floating
+    /// ones raised above them and fullscreen windows on top
+    pub fn stack_level(&self, client: &Client) -> u8 {
+        if client.fullscreen {
+            2
+        } else if client.floating && *self != Layout::Float {
+            1
+        } else {
+            0
         }
+    }
 
-        // TODO: TEMP
-        let gap = 10;
-        let num_master = 1;
-        let master_fact = 0.5;
-
-        let num_windows = windows.len();
-        let mut g = 0;
-        let mut master_width = 0;
-
-        if num_windows > num_master {
-            master_width = if num_master > 0 {
-                g = gap;
-                ((screen.width - g) as f32 * master_fact) as u32
+    /// Lay out the frames of windows and send only the requests needed to
+    /// get them there. Borders are drawn outside a frame, so each is shrunk
+    /// to fit its tile. Floating windows keep their own geometry above the
+    /// tiled ones, and fullscreen windows cover the screen above the rest
+    pub fn apply(
+        &self,
+        conn: &dyn Backend,
+        windows: &mut [Client],
+        screen: &Rect,
+        settings: &TileSettings,
+        border: &BorderConfig,
+    ) {
+        for client in windows.iter_mut() {
+            // Fullscreen windows have neither border nor title bar
+            let (border_width, title_height) = if client.fullscreen {
+                (0, 0)
             } else {
-                0
+                (border.width, border.title_height)
             };
-        } else {
-            master_width = 0;
+            let extents_changed =
+                client.border_width != border_width || client.title_height != title_height;
+            if client.border_width != border_width {
+                conn.set_border_width(&client.frame, border_width);
+                client.border_width = border_width;
+            }
+            if extents_changed {
+                conn.set_frame_extents(&client.window, border_width, title_height);
+            }
+            // Monocle windows are mapped one at a time by their desktop
+            if *self != Layout::Monocle {
+                client.map(conn);
+            }
         }
 
-        let mut my = 0;
-        let mut ty = 0;
-        let mut r = 0;
-        let mut h = 0;
-
-        for (i, window) in windows.iter().enumerate() {
-            let mut window_changes = x::WindowChanges::default();
-            if i < num_master {
-                r = (usize::min(num_windows, num_master) - i) as u32;
-                h = (screen.height - my - gap * (r - 1)) / r;
-                window_changes = x::WindowChanges {
-                    x: screen.x,
-                    y: screen.y + my,
-                    width: master_width,
-                    height: h,
-                    border_width: 0,
-                    sibling: 0,
-                    stack_mode: 0,
-                };
-                my += h;
-            } else {
-                r = (num_windows - i) as u32;
-                h = (screen.height - ty - gap * (r - 1)) / r;
-                window_changes = x::WindowChanges {
-                    x: screen.x + master_width + g,
-                    y: screen.y + ty,
-                    width: screen.width - master_width - g,
-                    height: h,
-                    border_width: 0,
-                    sibling: 0,
-                    stack_mode: 0,
-                };
-                ty += h + gap;
+        let (fullscreen, rest): (Vec<&mut Client>, Vec<&mut Client>) =
+            windows.iter_mut().partition(|c| c.fullscreen);
+        let (floating, tiled): (Vec<&mut Client>, Vec<&mut Client>) = rest
+            .into_iter()
+            .partition(|c| c.floating || *self == Layout::Float);
+
+        let mut restacked = false;
+        for client in floating {
+            // Floating windows only need their title bar resized, or to go
+            // back to where they were before going fullscreen
+            if client.title_height != border.title_height {
+                let geometry = client.floating_geometry.unwrap_or(client.geometry());
+                client.configure(conn, geometry, border.title_height);
+            }
+            // The floating layout leaves windows stacked as they are
+            if *self == Layout::Float {
+                client.raised = false;
+            } else if !client.raised {
+                client.raise(conn);
+                restacked = true;
+            }
+        }
+
+        if let Some(tiles) = self.arrange(tiled.len(), screen, settings) {
+            let border_width = border.width.saturating_mul(2);
+            for (client, tile) in tiled.into_iter().zip(tiles) {
+                let mut geometry = tile
+                    .inset(0, border_width, border_width, 0)
+                    .at_least_min_size();
+                if settings.size_hints {
+                    geometry = client.constrain(geometry);
+                }
+                if client.geometry() != geometry || client.title_height != border.title_height {
+                    client.configure(conn, geometry, border.title_height);
+                }
+                client.set_tiled_geometry(geometry);
+                client.raised = false;
+            }
+        }
+
+        for client in fullscreen {
+            if client.geometry() != *screen || client.title_height != 0 {
+                client.configure(conn, *screen, 0);
+            }
+            // Fullscreen windows stay above any floating window just raised
+            if restacked {
+                client.raised = false;
             }
-            connection.stop_window_events(&window);
-            connection.map_window(&window);
-            connection.configure_window(&window, &window_changes);
-            connection.track_window_events(&window);
+            client.raise(conn);
+        }
+    }
+
+    /// Area of each of count windows, in window order. Floating leaves
+    /// windows where they are and returns None
+    pub fn arrange(
+        &self,
+        count: usize,
+        screen: &Rect,
+        settings: &TileSettings,
+    ) -> Option<Vec<Rect>> {
+        match self {
+            Layout::Float => None,
+            Layout::Monocle => Some(Layout::monocle(count, screen, settings)),
+            Layout::Tile => Some(Layout::tile(count, screen, settings)),
         }
     }
+
+    /// Only one window is ever visible, filling the screen
+    fn monocle(count: usize, screen: &Rect, settings: &TileSettings) -> Vec<Rect> {
+        let area = settings.gaps.area(screen, 1);
+        vec![area.at_least_min_size(); count]
+    }
+
+    /// Masters stacked in a column on the left, the rest on the right
+    fn tile(count: usize, screen: &Rect, settings: &TileSettings) -> Vec<Rect> {
+        let gap = settings.gaps.inner(count);
+        let area = settings.gaps.area(screen, count);
+        let masters = settings.num_master.min(count);
+        let stacked = count - masters;
+
+        // A column only gets split off when both have windows
+        let (master, stack) = if stacked == 0 {
+            (area, Rect::default())
+        } else if masters == 0 {
+            (Rect::default(), area)
+        } else {
+            let width = (area.width.saturating_sub(gap) as f32 * settings.master_fact) as u32;
+            area.split_left(width, gap)
+        };
+
+        let mut rects = master.split_rows(masters, gap);
+        rects.extend(stack.split_rows(stacked, gap));
+        rects.iter().map(Rect::at_least_min_size).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::{self, FakeBackend, FakeWindow, Request};
+    use crate::x;
+
+    const SCREEN: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 1000,
+        height: 800,
+    };
+
+    fn geometry(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect::new(x, y, width, height)
+    }
+
+    #[test]
+    fn tile_splits_master_and_stack() {
+        let tiled = Layout::Tile.arrange(3, &SCREEN, &TileSettings::default());
+        assert_eq!(
+            tiled,
+            Some(vec![
+                geometry(0, 0, 495, 800),
+                geometry(505, 0, 495, 395),
+                geometry(505, 405, 495, 395),
+            ])
+        );
+    }
+
+    #[test]
+    fn tile_uses_whole_width_for_one_column() {
+        let settings = TileSettings::default();
+        let tiled = Layout::Tile.arrange(1, &SCREEN, &settings);
+        assert_eq!(tiled, Some(vec![geometry(0, 0, 1000, 800)]));
+
+        let no_master = TileSettings {
+            num_master: 0,
+            ..settings
+        };
+        let tiled = Layout::Tile.arrange(2, &SCREEN, &no_master);
+        assert_eq!(
+            tiled,
+            Some(vec![geometry(0, 0, 1000, 395), geometry(0, 405, 1000, 395)])
+        );
+
+        assert_eq!(Layout::Float.arrange(2, &SCREEN, &settings), None);
+    }
+
+    #[test]
+    fn gaps_surround_windows_unless_alone() {
+        let mut settings = TileSettings::default();
+        settings.gaps.outer = Edges {
+            top: 20,
+            ..Edges::all(5)
+        };
+        settings.gaps.smart = true;
+
+        let tiled = Layout::Tile.arrange(2, &SCREEN, &settings);
+        assert_eq!(
+            tiled,
+            Some(vec![geometry(5, 20, 490, 775), geometry(505, 20, 490, 775)])
+        );
+        let alone = Layout::Tile.arrange(1, &SCREEN, &settings);
+        assert_eq!(alone, Some(vec![SCREEN]));
+
+        settings.gaps.smart = false;
+        let monocle = Layout::Monocle.arrange(2, &SCREEN, &settings);
+        assert_eq!(monocle, Some(vec![geometry(5, 20, 990, 775); 2]));
+
+        settings.gaps.enabled = false;
+        let tiled = Layout::Tile.arrange(2, &SCREEN, &settings);
+        assert_eq!(
+            tiled,
+            Some(vec![geometry(0, 0, 500, 800), geometry(500, 0, 500, 800)])
+        );
+    }
+
+    #[test]
+    fn tile_survives_crowded_small_screen() {
+        let screen = Rect::new(-200, -100, 50, 40);
+        let tiled = Layout::Tile
+            .arrange(30, &screen, &TileSettings::default())
+            .unwrap();
+        assert_eq!(tiled.len(), 30);
+        assert!(tiled.iter().all(|r| r.width >= 1 && r.height >= 1));
+        assert!(tiled
+            .iter()
+            .all(|r| r.x >= screen.x && r.y < screen.bottom()));
+    }
+
+    #[test]
+    fn apply_only_configures_changed_windows() {
+        let fake = FakeBackend::new();
+        let mut windows: Vec<Client> = (1..=3)
+            .map(|id| Client::new(&*fake, fake.create_window(id, FakeWindow::default()), 0))
+            .collect();
+        windows[2].floating = true;
+        let settings = TileSettings::default();
+        let mut border = BorderConfig {
+            width: 0,
+            title_height: 0,
+            ..BorderConfig::default()
+        };
+        fake.take_requests();
+
+        Layout::Tile.apply(&*fake, &mut windows, &SCREEN, &settings, &border);
+        assert_eq!(
+            fake.take_requests(),
+            vec![
+                Request::Map(fake::frame(1)),
+                Request::Map(fake::frame(2)),
+                Request::Map(fake::frame(3)),
+                Request::Raise(fake::frame(3)),
+                Request::Configure(fake::frame(1), geometry(0, 0, 495, 800)),
+                Request::Configure(1, geometry(0, 0, 495, 800)),
+                Request::Configure(fake::frame(2), geometry(505, 0, 495, 800)),
+                Request::Configure(2, geometry(0, 0, 495, 800)),
+            ]
+        );
+
+        Layout::Tile.apply(&*fake, &mut windows, &SCREEN, &settings, &border);
+        assert_eq!(fake.take_requests(), vec![]);
+
+        windows.remove(1);
+        border.width = 2;
+        border.title_height = 10;
+        Layout::Tile.apply(&*fake, &mut windows, &SCREEN, &settings, &border);
+        assert_eq!(
+            fake.take_requests(),
+            vec![
+                Request::BorderWidth(fake::frame(1), 2),
+                Request::FrameExtents(1, 2, 10),
+                Request::BorderWidth(fake::frame(3), 2),
+                Request::FrameExtents(3, 2, 10),
+                Request::Configure(fake::frame(3), geometry(0, 0, 100, 100)),
+                Request::Configure(3, geometry(0, 10, 100, 90)),
+                Request::Configure(fake::frame(1), geometry(0, 0, 996, 796)),
+                Request::Configure(1, geometry(0, 10, 996, 786)),
+            ]
+        );
+    }
+
+    #[test]
+    fn tiled_windows_follow_size_hints() {
+        let fake = FakeBackend::new();
+        let terminal = FakeWindow {
+            size_hints: x::SizeHints {
+                base: Some((4, 4)),
+                increment: Some((10, 20)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut windows = vec![Client::new(&*fake, fake.create_window(1, terminal), 0)];
+        let mut settings = TileSettings::default();
+        let border = BorderConfig {
+            width: 0,
+            title_height: 0,
+            ..BorderConfig::default()
+        };
+
+        Layout::Tile.apply(&*fake, &mut windows, &SCREEN, &settings, &border);
+        assert_eq!(windows[0].geometry(), geometry(0, 0, 994, 784));
+
+        settings.size_hints = false;
+        Layout::Tile.apply(&*fake, &mut windows, &SCREEN, &settings, &border);
+        assert_eq!(windows[0].geometry(), SCREEN);
+    }
 }
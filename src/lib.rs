@@ -1,14 +1,32 @@
+use std::env;
+use std::os::unix::process::CommandExt;
+use std::process;
 use std::rc::Rc;
 
+pub mod backend;
+mod client;
 pub mod command;
+pub mod config;
 mod desktop;
+mod drag;
+#[cfg(test)]
+mod fake;
 pub mod key;
 pub mod layout;
+pub mod rect;
+mod session;
+mod signal;
+pub mod spawn;
 mod x;
 
+use backend::Backend;
+use client::Client;
+use command::Command;
+use config::{Config, FocusModel};
 use desktop::*;
+use drag::{Drag, DragKind};
 use key::*;
-use layout::Layout;
+use session::{SavedWindow, Session};
 
 #[macro_export]
 macro_rules! bindings {
@@ -23,102 +41,209 @@ macro_rules! bindings {
     };
 }
 
-// TODO: Might needo some other screen specific things
-// i.e. info about bars and stuff
-/// struct that represents the usable portion of the screen
-#[derive(Clone, Debug)]
-pub struct Screen {
-    width: u32,
-    height: u32,
-    x: u32,
-    y: u32,
-}
-
 pub struct Worm {
-    connection: Rc<x::Connection>,
+    connection: Rc<dyn Backend>,
     desktops: Desktops,
-    binds: KeyMap,
-    screen: Screen,
+    config: Config,
+    running: bool,
+    /// Window being moved or resized with the pointer
+    drag: Option<Drag>,
 }
 
 impl Worm {
-    pub fn new(binds: KeyMap) -> Worm {
-        let connection = x::Connection::new();
-        let connection = Rc::new(connection);
-        connection.setup(&binds);
-
-        let existing_windows: Vec<x::Window> = Vec::new();
-
-        // TODO: Change this to account for actual screen size, maybe get rid of Screen
-        // struct and just use x::Window
-        let root_window = connection.root_window();
-        let screen = Screen {
-            x: root_window.x,
-            y: root_window.y,
-            width: root_window.width,
-            height: root_window.height,
-        };
+    pub fn new(config: Config) -> Worm {
+        signal::install();
+
+        Worm::with_backend(config, Rc::new(x::Connection::new()))
+    }
+
+    /// Take over the display behind connection and manage what is already
+    /// on it
+    fn with_backend(config: Config, connection: Rc<dyn Backend>) -> Worm {
+        connection.setup(&config.binds);
 
         let mut desktops: Vec<Desktop> = Vec::new();
-        for i in 0..9 {
+        for name in config.desktops.iter() {
             desktops.push(Desktop::new(
-                &i.to_string(),
-                false,
-                Layout::Tile,
-                existing_windows.clone(),
+                name,
+                config.layout,
+                config.tile_settings(name),
+                config.border,
                 connection.clone(),
-                &screen,
             ))
         }
 
-        let desktops = Desktops::new(desktops, 0);
+        let desktops = Desktops::new(desktops, &connection.get_monitors());
 
         let mut wm = Worm {
             connection: connection.clone(),
             desktops,
-            binds,
-            screen,
+            config,
+            running: true,
+            drag: None,
         };
 
-        // Manage existing windows and add them to 1sr desktop
-        let windows = connection.get_existing_windows();
-        for window in windows.iter() {
-            wm.manage(window);
-        }
+        // Pick up where the previous instance left off when restarted
+        let session = connection
+            .take_saved_state()
+            .and_then(|state| match state.parse::<Session>() {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    eprintln!("worm: ignoring saved state: {}", e);
+                    None
+                }
+            });
+        wm.manage_existing(session.as_ref());
 
         wm
     }
 
     pub fn run(&mut self) {
-        loop {
+        while self.running {
+            if signal::take_reload() {
+                self.reload_config();
+            }
+
             self.connection.flush();
 
-            let event = match self.connection.wait_for_event() {
+            let event = match self.connection.wait_for_event(signal::wakeup_fd()) {
                 Some(e) => e,
-                None => continue,
+                None => {
+                    signal::drain();
+                    continue;
+                }
             };
 
-            println!("EVENT: {:?}", event);
+            self.handle_event(event);
+        }
+    }
 
-            match event {
-                x::XEvent::ConfigureRequest(w, wc) => self.configure_request(w, wc),
-                x::XEvent::KeyPress(k) => self.key_press_event(k),
-                x::XEvent::MapRequest(w) => self.map_request(&w),
-                x::XEvent::UnmapNotify(w) => self.unmap_notify(&w),
-                x::XEvent::DestroyNotify(w) => self.destroy_notify(&w),
-            };
+    fn handle_event(&mut self, event: x::XEvent) {
+        match event {
+            x::XEvent::ConfigureRequest(w, wc) => self.configure_request(w, wc),
+            x::XEvent::ConfigureNotify(w) => self.configure_notify(&w),
+            x::XEvent::KeyPress(k) => self.key_press_event(k),
+            x::XEvent::MapRequest(w) => self.map_request(&w),
+            x::XEvent::UnmapNotify(w) => self.unmap_notify(&w),
+            x::XEvent::DestroyNotify(w) => self.destroy_notify(&w),
+            x::XEvent::PropertyNotify(w, p) => self.property_notify(&w, p),
+            x::XEvent::Expose(w) => self.desktops.redraw_title(&w),
+            x::XEvent::ScreenChange => self.screen_change(),
+            x::XEvent::ClientMessage(w, r) => self.client_message(&w, r),
+            x::XEvent::ButtonPress(w, b, m, p) => self.button_press(&w, b, m, p),
+            x::XEvent::EnterNotify(w) => self.enter_notify(&w),
+            x::XEvent::MotionNotify(p) => self.motion_notify(p),
+            x::XEvent::ButtonRelease => self.button_release(),
+        };
+    }
+
+    /// Re-read the config file and apply it, keeping every managed window on
+    /// the desktop it is on. The old config stays in place if loading fails
+    pub fn reload_config(&mut self) {
+        let path = match self.config.path.clone().or_else(Config::default_path) {
+            Some(p) => p,
+            None => return,
+        };
+        let config = if path.exists() || self.config.path.is_some() {
+            match Config::load(&path) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("worm: {}", e);
+                    return;
+                }
+            }
+        } else {
+            Config::default()
+        };
+
+        let root = self.connection.root_window();
+        let managed = self.desktops.managed_windows();
+        for window in std::iter::once(&root).chain(managed.iter()) {
+            self.connection.ungrab_keys(window);
+            self.connection.grab_keys(window, &config.binds);
         }
+        let click_to_focus = config.focus_model == FocusModel::Click;
+        for frame in self.desktops.managed_frames() {
+            self.connection
+                .grab_buttons(&frame, config.mouse_modifier, click_to_focus);
+        }
+
+        self.desktops.reconfigure(
+            &config.desktops,
+            config.layout,
+            &config.desktop_tile_settings(),
+            config.border,
+        );
+        self.config = config;
+        self.desktops.apply_layouts();
+    }
+
+    /// Leave every window mapped and stop being the WM, run returns after
+    pub fn quit(&mut self) {
+        self.shutdown();
+        self.running = false;
+    }
+
+    /// Save where every window is and re-exec worm, which restores it
+    pub fn restart(&mut self) {
+        let session = self.desktops.save_session();
+        self.shutdown();
+        self.connection.set_saved_state(&session.to_string());
+        self.connection.flush();
+
+        let mut args = env::args_os();
+        let program = match args.next() {
+            Some(p) => p,
+            None => env::current_exe()
+                .map(|p| p.into_os_string())
+                .unwrap_or_default(),
+        };
+        let e = process::Command::new(program).args(args).exec();
+
+        // Windows are already released, so all that is left is to exit
+        eprintln!("worm: could not restart: {}", e);
+        self.running = false;
+    }
+
+    fn shutdown(&mut self) {
+        self.desktops.release_windows();
+        self.connection.release();
     }
 
     fn manage(&mut self, window: &x::Window) {
-        if self.is_managed(&window) {
+        self.manage_placed(window, None);
+    }
+
+    /// Manage a window, placing it as saved before a restart if given,
+    /// otherwise as the rules say
+    fn manage_placed(&mut self, window: &x::Window, saved: Option<&SavedWindow>) {
+        if self.is_managed(window) {
             panic!("Already managed window attempting to be managed again");
         }
 
-        self.connection.grab_keys(window, &self.binds);
+        self.connection.grab_keys(window, &self.config.binds);
         self.connection.register_window(window);
         self.connection.track_window_events(window);
-        self.desktops.add_window(window.clone());
+
+        let title_height = self.config.border.title_height;
+        let mut client = Client::new(&*self.connection, *window, title_height);
+        self.connection.grab_buttons(
+            &client.frame,
+            self.config.mouse_modifier,
+            self.config.focus_model == FocusModel::Click,
+        );
+        let mut desktop = self.desktops.focused_desktop;
+        if let Some(rule) = self.config.rule_for(&client) {
+            if let Some(floating) = rule.floating {
+                client.floating = floating;
+            }
+            desktop = rule.desktop.unwrap_or(desktop);
+        }
+        if let Some(saved) = saved {
+            client.floating = saved.floating;
+            desktop = saved.desktop;
+        }
+        self.desktops.add_window_to(desktop, client);
     }
 
     fn unmanage(&mut self, window: &x::Window) {
@@ -131,25 +256,70 @@ impl Worm {
         self.desktops.remove_window(window);
     }
 
-    fn manage_existing(&mut self) {
-        let windows = self.connection.get_existing_windows();
+    /// Manage the windows that were mapped before worm started, along with
+    /// any hidden windows a previous instance saved
+    fn manage_existing(&mut self, session: Option<&Session>) {
+        let mut windows = self.connection.get_existing_windows();
+
+        let session = match session {
+            Some(s) => s,
+            None => {
+                for (window, viewable) in windows.iter() {
+                    if *viewable {
+                        self.manage(window);
+                    }
+                }
+                return;
+            }
+        };
+
+        // Saved windows go first, in their old order, so indices line up
+        windows.sort_by_key(|(w, _)| match session.window(w.as_xcb_window()) {
+            Some(saved) => (0, saved.desktop, saved.index),
+            None => (1, 0, 0),
+        });
+        for (window, viewable) in windows.iter() {
+            match session.window(window.as_xcb_window()) {
+                Some(saved) => self.manage_placed(window, Some(saved)),
+                None if *viewable => self.manage(window),
+                None => {}
+            }
+        }
 
-        for window in windows.iter() {
-            self.manage(window);
+        for (i, saved) in session.desktops.iter().enumerate() {
+            self.desktops.restore_desktop(i, saved);
         }
+        self.desktops.focus_desktop(session.current_desktop);
     }
 
-    fn configure_request(&self, window: x::Window, window_changes: x::WindowChanges) {
-        // Don't change anything
+    fn configure_request(&mut self, window: x::Window, window_changes: x::WindowChanges) {
+        // Managed windows keep the geometry their layout gives them
+        if self.desktops.enforce_layout(&window) {
+            return;
+        }
+        // Floating windows move their frame along with them
+        if self.desktops.configure_floating(&window, &window_changes) {
+            return;
+        }
         self.connection.configure_window(&window, &window_changes);
     }
 
+    fn configure_notify(&mut self, window: &x::Window) {
+        self.desktops.update_geometry(window);
+    }
+
     fn key_press_event(&mut self, key: Key) {
-        let cmd = self.binds.key_map[&key].clone();
-        cmd.command(self);
+        // Grabs can briefly outlive their binding while the config reloads
+        if let Some(cmd) = self.config.binds.key_map.get(&key).cloned() {
+            cmd.command(self);
+        }
     }
 
     fn map_request(&mut self, window: &x::Window) {
+        // Windows on hidden desktops may ask to be mapped again
+        if self.is_managed(window) {
+            return;
+        }
         self.manage(window);
     }
 
@@ -161,6 +331,78 @@ impl Worm {
         self.unmanage(window);
     }
 
+    fn property_notify(&mut self, window: &x::Window, property: x::Property) {
+        self.desktops.update_property(window, property);
+    }
+
+    /// Carry out what pagers and tools like wmctrl ask for
+    fn client_message(&mut self, window: &x::Window, request: x::ClientRequest) {
+        match request {
+            x::ClientRequest::CurrentDesktop(i) => Command::FocusDesktop(i).command(self),
+            x::ClientRequest::Activate => self.desktops.activate_window(window),
+            x::ClientRequest::Close => self.desktops.close_window(window),
+            x::ClientRequest::WindowDesktop(i) => {
+                self.desktops.move_window_to_desktop(window, i, false)
+            }
+            x::ClientRequest::Fullscreen(f) => self.desktops.set_fullscreen(window, f),
+        }
+    }
+
+    /// Start moving or resizing the window in frame, any other click focuses
+    /// it and goes on to the window
+    fn button_press(
+        &mut self,
+        frame: &x::Window,
+        button: u8,
+        modifiers: ModMask,
+        position: (i32, i32),
+    ) {
+        let drag = DragKind::for_button(button).filter(|_| modifiers == self.config.mouse_modifier);
+        let kind = match drag {
+            Some(k) => k,
+            None => {
+                self.desktops.focus_frame(frame);
+                self.connection.replay_pointer();
+                return;
+            }
+        };
+        let geometry = match self.desktops.start_drag(frame) {
+            Some(g) => g,
+            None => return,
+        };
+        let cursor = match kind {
+            DragKind::Move => x::Cursor::Move,
+            DragKind::Resize => x::Cursor::Resize,
+        };
+        if self.connection.grab_pointer(cursor) {
+            self.drag = Some(Drag::new(*frame, kind, position, geometry));
+        }
+    }
+
+    fn motion_notify(&mut self, position: (i32, i32)) {
+        if let Some(drag) = &self.drag {
+            self.desktops
+                .drag_window(&drag.frame, drag.geometry_at(position));
+        }
+    }
+
+    fn enter_notify(&mut self, window: &x::Window) {
+        if self.config.focus_model == FocusModel::Sloppy {
+            self.desktops.activate_window(window);
+        }
+    }
+
+    fn button_release(&mut self) {
+        if self.drag.take().is_some() {
+            self.connection.ungrab_pointer();
+        }
+    }
+
+    fn screen_change(&mut self) {
+        let monitors = self.connection.get_monitors();
+        self.desktops.set_monitors(&monitors);
+    }
+
     fn is_managed(&self, window: &x::Window) -> bool {
         self.desktops.contains(window)
     }
@@ -168,8 +410,193 @@ impl Worm {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::config::Rule;
+    use crate::fake::{frame, FakeBackend, FakeWindow, Request, ROOT};
+    use crate::x::{ClientRequest, XEvent};
+
+    fn worm(fake: &Rc<FakeBackend>, config: Config) -> Worm {
+        Worm::with_backend(config, fake.clone())
+    }
+
+    /// Handle every event sent to the fake, as run would
+    fn handle_events(wm: &mut Worm, fake: &FakeBackend) {
+        loop {
+            wm.connection.flush();
+            match fake.wait_for_event(-1) {
+                Some(event) => wm.handle_event(event),
+                None => return,
+            }
+        }
+    }
+
+    fn map(wm: &mut Worm, fake: &FakeBackend, id: x::WindowId, properties: FakeWindow) {
+        fake.send_event(XEvent::MapRequest(fake.create_window(id, properties)));
+        handle_events(wm, fake);
+    }
+
+    fn window(id: x::WindowId) -> x::Window {
+        x::Window {
+            window: id,
+            ..Default::default()
+        }
+    }
+
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn map_request_manages_window_by_rules() {
+        let fake = FakeBackend::new();
+        let mut config = Config::default();
+        config.rules.push(Rule {
+            class: Some("Gimp".to_string()),
+            instance: None,
+            title: None,
+            desktop: Some(1),
+            floating: Some(true),
+        });
+        let mut wm = worm(&fake, config);
+        fake.take_requests();
+
+        map(&mut wm, &fake, 1, FakeWindow::default());
+        let requests = fake.take_requests();
+        assert!(requests.contains(&Request::GrabKeys(1)));
+        let grab = Request::GrabButtons(frame(1), wm.config.mouse_modifier, true);
+        assert!(requests.contains(&grab));
+        assert!(fake.is_mapped(1));
+        assert_eq!(fake.focused(), Some(1));
+
+        let gimp = FakeWindow {
+            class: "Gimp".to_string(),
+            ..Default::default()
+        };
+        map(&mut wm, &fake, 2, gimp);
+        assert!(fake.take_requests().contains(&Request::WindowDesktop(2, 1)));
+        assert!(!fake.is_mapped(2));
+
+        // Floating windows are raised above the tiled ones
+        wm.desktops.focus_desktop(1);
+        assert!(fake.take_requests().contains(&Request::Raise(frame(2))));
+    }
+
+    #[test]
+    fn client_messages_switch_desktops_and_close_windows() {
+        let fake = FakeBackend::new();
+        let mut wm = worm(&fake, Config::default());
+        map(&mut wm, &fake, 1, FakeWindow::default());
+        fake.take_requests();
+
+        fake.send_event(XEvent::ClientMessage(
+            window(ROOT),
+            ClientRequest::CurrentDesktop(1),
+        ));
+        handle_events(&mut wm, &fake);
+        assert!(fake.take_requests().contains(&Request::CurrentDesktop(1)));
+        assert!(!fake.is_mapped(1));
+
+        fake.send_event(XEvent::ClientMessage(window(1), ClientRequest::Close));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.take_requests(), vec![Request::Delete(1)]);
+    }
+
+    #[test]
+    fn modifier_click_drags_and_plain_click_focuses() {
+        let fake = FakeBackend::new();
+        let mut wm = worm(&fake, Config::default());
+        map(&mut wm, &fake, 1, FakeWindow::default());
+        map(&mut wm, &fake, 2, FakeWindow::default());
+        assert_eq!(fake.focused(), Some(1));
+        fake.take_requests();
+
+        fake.send_event(XEvent::ButtonPress(window(frame(2)), 1, 0, (5, 5)));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.focused(), Some(2));
+        assert!(fake.take_requests().contains(&Request::ReplayPointer));
+        assert!(wm.drag.is_none());
+
+        let modifier = wm.config.mouse_modifier;
+        fake.send_event(XEvent::ButtonPress(window(frame(1)), 1, modifier, (5, 5)));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.focused(), Some(1));
+        let requests = fake.take_requests();
+        assert!(requests.contains(&Request::GrabPointer(x::Cursor::Move)));
+        assert!(!requests.contains(&Request::ReplayPointer));
+
+        fake.send_event(XEvent::MotionNotify((15, 25)));
+        fake.send_event(XEvent::ButtonRelease);
+        handle_events(&mut wm, &fake);
+        let requests = fake.take_requests();
+        let moved = requests.iter().any(|r| match r {
+            Request::Configure(w, _) => *w == frame(1),
+            _ => false,
+        });
+        assert!(moved);
+        assert_eq!(requests.last(), Some(&Request::UngrabPointer));
+        assert!(wm.drag.is_none());
+    }
+
+    #[test]
+    fn reload_config_regrabs_keys_and_buttons() {
+        let path = env::temp_dir().join(format!("worm-reload-{}.toml", process::id()));
+        std::fs::write(&path, "focus = \"sloppy\"\nmouse_modifier = \"Super\"\n").unwrap();
+        let fake = FakeBackend::new();
+        let config = Config {
+            path: Some(path.clone()),
+            ..Config::default()
+        };
+        let mut wm = worm(&fake, config);
+        map(&mut wm, &fake, 1, FakeWindow::default());
+        fake.take_requests();
+
+        wm.reload_config();
+        std::fs::remove_file(&path).unwrap();
+        let requests = fake.take_requests();
+        for window in [ROOT, 1].iter() {
+            assert!(requests.contains(&Request::UngrabKeys(*window)));
+            assert!(requests.contains(&Request::GrabKeys(*window)));
+        }
+        let modifier = Modifier::Mod4.get_mod_mask();
+        assert!(requests.contains(&Request::GrabButtons(frame(1), modifier, false)));
+        assert_eq!(wm.config.focus_model, FocusModel::Sloppy);
+    }
+
+    #[test]
+    fn entering_a_window_focuses_it_only_when_sloppy() {
+        let fake = FakeBackend::new();
+        let mut wm = worm(&fake, Config::default());
+        map(&mut wm, &fake, 1, FakeWindow::default());
+        map(&mut wm, &fake, 2, FakeWindow::default());
+
+        fake.send_event(XEvent::EnterNotify(window(2)));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.focused(), Some(1));
+
+        wm.config.focus_model = FocusModel::Sloppy;
+        fake.send_event(XEvent::EnterNotify(window(2)));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.focused(), Some(2));
+    }
+
+    #[test]
+    fn crossings_caused_by_relayout_are_ignored() {
+        let fake = FakeBackend::new();
+        let config = Config {
+            focus_model: FocusModel::Sloppy,
+            ..Config::default()
+        };
+        let mut wm = worm(&fake, config);
+        map(&mut wm, &fake, 1, FakeWindow::default());
+        map(&mut wm, &fake, 2, FakeWindow::default());
+        assert_eq!(fake.focused(), Some(1));
+
+        // A new window shrinks the others, moving one under the pointer
+        let new = fake.create_window(3, FakeWindow::default());
+        wm.handle_event(XEvent::MapRequest(new));
+        fake.send_event(XEvent::EnterNotify(window(2)));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.focused(), Some(1));
+
+        // Once the layout settled the pointer moving in focuses again
+        fake.send_event(XEvent::EnterNotify(window(2)));
+        handle_events(&mut wm, &fake);
+        assert_eq!(fake.focused(), Some(2));
     }
 }
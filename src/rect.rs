@@ -0,0 +1,146 @@
+/// Smallest width or height a window is ever given, X rejects zero sizes
+pub const MIN_SIZE: u32 = 1;
+
+/// Position and size of an area of the screen. Positions are signed since
+/// monitors left of or above the origin have negative coordinates, and all
+/// arithmetic saturates rather than overflowing
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// First column past the right edge
+    pub fn right(&self) -> i32 {
+        offset(self.x, self.width)
+    }
+
+    /// First row past the bottom edge
+    pub fn bottom(&self) -> i32 {
+        offset(self.y, self.height)
+    }
+
+    /// The same rect grown to at least the minimum window size
+    pub fn at_least_min_size(&self) -> Rect {
+        Rect {
+            width: self.width.max(MIN_SIZE),
+            height: self.height.max(MIN_SIZE),
+            ..*self
+        }
+    }
+
+    /// Move every edge inwards by amount
+    pub fn shrink(&self, amount: u32) -> Rect {
+        self.inset(amount, amount, amount, amount)
+    }
+
+    /// Move each edge inwards by its own amount
+    pub fn inset(&self, top: u32, right: u32, bottom: u32, left: u32) -> Rect {
+        Rect {
+            x: offset(self.x, left),
+            y: offset(self.y, top),
+            width: self.width.saturating_sub(left.saturating_add(right)),
+            height: self.height.saturating_sub(top.saturating_add(bottom)),
+        }
+    }
+
+    /// The same rect moved, and shrunk if it does not fit, to lie within area
+    pub fn clamp_into(&self, area: &Rect) -> Rect {
+        let width = self.width.min(area.width);
+        let height = self.height.min(area.height);
+        Rect {
+            x: self.x.clamp(area.x, offset(area.x, area.width - width)),
+            y: self.y.clamp(area.y, offset(area.y, area.height - height)),
+            width,
+            height,
+        }
+    }
+
+    /// Split off a column of width on the left, returning it and the area
+    /// right of it after a gap
+    pub fn split_left(&self, width: u32, gap: u32) -> (Rect, Rect) {
+        let width = width.min(self.width);
+        let used = width.saturating_add(gap).min(self.width);
+        let left = Rect { width, ..*self };
+        let right = Rect {
+            x: offset(self.x, used),
+            width: self.width - used,
+            ..*self
+        };
+        (left, right)
+    }
+
+    /// Divide the height between count rows gap apart, giving the remainder
+    /// of uneven splits to the last rows
+    pub fn split_rows(&self, count: usize, gap: u32) -> Vec<Rect> {
+        let mut y = self.y;
+        let mut remaining = self.height;
+        (0..count)
+            .map(|i| {
+                let rows = (count - i) as u32;
+                let gaps = gap.saturating_mul(rows - 1);
+                let height = remaining.saturating_sub(gaps) / rows;
+                let row = Rect { y, height, ..*self };
+                // Rows that do not fit pile up on the last line of the area
+                let used = height.saturating_add(gap).min(remaining.saturating_sub(1));
+                y = offset(y, used);
+                remaining -= used;
+                row
+            })
+            .collect()
+    }
+}
+
+/// Change a size or gap by delta, stopping at zero
+pub fn grow(size: u32, delta: i32) -> u32 {
+    if delta < 0 {
+        size.saturating_sub(delta.unsigned_abs())
+    } else {
+        size.saturating_add(delta as u32)
+    }
+}
+
+fn offset(position: i32, amount: u32) -> i32 {
+    position.saturating_add(amount.min(i32::MAX as u32) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_never_overflow() {
+        let area = Rect::new(-1920, 0, 1920, 1080);
+        let (left, right) = area.split_left(960, 10);
+        assert_eq!(left, Rect::new(-1920, 0, 960, 1080));
+        assert_eq!(right, Rect::new(-950, 0, 950, 1080));
+        assert_eq!(right.right(), 0);
+
+        let rows = area.split_rows(3, 10);
+        assert_eq!(rows[1], Rect::new(-1920, 363, 1920, 353));
+        assert_eq!(rows[2].bottom(), 1080);
+
+        let window = Rect::new(1900, -50, 100, 2000);
+        assert_eq!(window.clamp_into(&area), Rect::new(-100, 0, 100, 1080));
+
+        let tiny = Rect::new(0, 0, 5, 5);
+        assert_eq!(tiny.shrink(10), Rect::new(10, 10, 0, 0));
+        assert_eq!(tiny.split_left(100, 100).1.width, 0);
+        let rows = tiny.split_rows(20, 10);
+        assert!(rows
+            .iter()
+            .all(|r| r.at_least_min_size().height == MIN_SIZE));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::layout::Layout;
+use crate::x;
+
+/// State carried across a restart so windows come back where they were
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    pub current_desktop: usize,
+    pub desktops: Vec<SavedDesktop>,
+    pub windows: Vec<SavedWindow>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SavedDesktop {
+    pub layout: Layout,
+    pub focused_window: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SavedWindow {
+    pub window: x::WindowId,
+    pub desktop: usize,
+    pub index: usize,
+    pub floating: bool,
+}
+
+impl Session {
+    pub fn window(&self, window: x::WindowId) -> Option<&SavedWindow> {
+        self.windows.iter().find(|w| w.window == window)
+    }
+}
+
+/// One record per line:
+///   current <desktop>
+///   desktop <layout> <focused index or ->
+///   window <id> <desktop> <index> <floating>
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "current {}", self.current_desktop)?;
+        for desktop in self.desktops.iter() {
+            match desktop.focused_window {
+                Some(i) => writeln!(f, "desktop {} {}", desktop.layout, i)?,
+                None => writeln!(f, "desktop {} -", desktop.layout)?,
+            }
+        }
+        for w in self.windows.iter() {
+            writeln!(
+                f,
+                "window {} {} {} {}",
+                w.window, w.desktop, w.index, w.floating as u8
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Session, String> {
+        let mut session = Session::default();
+        for line in s.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let number = |i: usize| -> Result<usize, String> {
+                words
+                    .get(i)
+                    .and_then(|w| w.parse().ok())
+                    .ok_or_else(|| format!("malformed line \"{}\"", line))
+            };
+            match words.first() {
+                Some(&"current") => session.current_desktop = number(1)?,
+                Some(&"desktop") => session.desktops.push(SavedDesktop {
+                    layout: words.get(1).copied().unwrap_or_default().parse()?,
+                    focused_window: number(2).ok(),
+                }),
+                Some(&"window") => session.windows.push(SavedWindow {
+                    window: number(1)? as x::WindowId,
+                    desktop: number(2)?,
+                    index: number(3)?,
+                    floating: number(4)? != 0,
+                }),
+                None => {}
+                Some(_) => return Err(format!("malformed line \"{}\"", line)),
+            }
+        }
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_round_trip() {
+        let session = Session {
+            current_desktop: 1,
+            desktops: vec![
+                SavedDesktop {
+                    layout: Layout::Tile,
+                    focused_window: Some(1),
+                },
+                SavedDesktop {
+                    layout: Layout::Monocle,
+                    focused_window: None,
+                },
+            ],
+            windows: vec![
+                SavedWindow {
+                    window: 0x200003,
+                    desktop: 0,
+                    index: 0,
+                    floating: false,
+                },
+                SavedWindow {
+                    window: 0x400001,
+                    desktop: 0,
+                    index: 1,
+                    floating: true,
+                },
+            ],
+        };
+
+        let parsed: Session = session.to_string().parse().unwrap();
+        assert_eq!(parsed, session);
+        assert!("window 12 zero 0 0".parse::<Session>().is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+// Signals are only recorded by the handler, the event loop acts on them. A
+// byte is written to a pipe so a loop blocked waiting for X events wakes up
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static WAKE_READ: AtomicI32 = AtomicI32::new(-1);
+static WAKE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if signal == libc::SIGCHLD {
+        reap_children();
+        return;
+    }
+    if signal == libc::SIGHUP {
+        RELOAD.store(true, Ordering::SeqCst);
+    }
+
+    let fd = WAKE_WRITE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = 0u8;
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Collect every exited child so spawned programs do not linger as zombies
+fn reap_children() {
+    unsafe {
+        // waitpid may clobber errno of whatever the handler interrupted
+        let errno = *libc::__errno_location();
+        while libc::waitpid(-1, std::ptr::null_mut(), libc::WNOHANG) > 0 {}
+        *libc::__errno_location() = errno;
+    }
+}
+
+/// Install the signal handlers, must be called before any other function here
+pub fn install() {
+    let mut fds = [0 as libc::c_int; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            panic!("Could not create signal pipe");
+        }
+        for fd in fds.iter() {
+            libc::fcntl(*fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    WAKE_READ.store(fds[0], Ordering::SeqCst);
+    WAKE_WRITE.store(fds[1], Ordering::SeqCst);
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as usize;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) != 0 {
+            panic!("Could not install SIGHUP handler");
+        }
+        action.sa_flags |= libc::SA_NOCLDSTOP;
+        if libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) != 0 {
+            panic!("Could not install SIGCHLD handler");
+        }
+    }
+
+    // Programs started before worm exec'd, e.g. from xinitrc, may already
+    // have exited
+    reap_children();
+}
+
+/// File descriptor that becomes readable whenever a signal arrived
+pub fn wakeup_fd() -> RawFd {
+    WAKE_READ.load(Ordering::SeqCst)
+}
+
+/// Empty the wakeup pipe after it was reported readable
+pub fn drain() {
+    let fd = wakeup_fd();
+    let mut buf = [0u8; 64];
+    unsafe {
+        while libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) > 0 {}
+    }
+}
+
+/// Returns true once for every batch of SIGHUPs received
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,160 @@
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::ptr;
+
+/// An external program to launch, detached from the WM
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spawn {
+    /// Program and arguments, or the command line given to sh in shell mode
+    pub argv: Vec<String>,
+    pub shell: bool,
+    /// Variables added to the environment the program inherits
+    pub env: Vec<(String, String)>,
+}
+
+impl Spawn {
+    /// Build from words like "FOO=bar urxvt -e htop", leading NAME=VALUE
+    /// words are taken as environment additions
+    pub fn from_words(words: &[&str], shell: bool) -> Result<Spawn, String> {
+        let mut env = Vec::new();
+        let mut rest = words;
+        while let Some((word, tail)) = rest.split_first() {
+            match word.find('=') {
+                Some(i) if i > 0 && !word[..i].contains('/') => {
+                    env.push((word[..i].to_string(), word[i + 1..].to_string()));
+                    rest = tail;
+                }
+                _ => break,
+            }
+        }
+
+        if rest.is_empty() {
+            return Err(String::from("nothing to spawn"));
+        }
+        let argv = if shell {
+            vec![rest.join(" ")]
+        } else {
+            rest.iter().map(|w| w.to_string()).collect()
+        };
+
+        Ok(Spawn { argv, shell, env })
+    }
+
+    /// Start the program in its own session. Children are reaped by the
+    /// SIGCHLD handler, failures to start are logged
+    pub fn spawn(&self) {
+        if let Err(e) = self.try_spawn() {
+            eprintln!("worm: could not start \"{}\": {}", self.argv.join(" "), e);
+        }
+    }
+
+    fn try_spawn(&self) -> io::Result<()> {
+        let invalid = |_| io::Error::new(io::ErrorKind::InvalidInput, "contains a nul byte");
+
+        // Everything the child needs is allocated before forking
+        let argv: Vec<CString> = if self.shell {
+            vec![
+                CString::new("/bin/sh").map_err(invalid)?,
+                CString::new("-c").map_err(invalid)?,
+                CString::new(self.argv.join(" ")).map_err(invalid)?,
+            ]
+        } else {
+            self.argv
+                .iter()
+                .map(|a| CString::new(a.as_str()).map_err(invalid))
+                .collect::<io::Result<_>>()?
+        };
+        let mut argv_ptrs: Vec<*const libc::c_char> = argv.iter().map(|a| a.as_ptr()).collect();
+        argv_ptrs.push(ptr::null());
+        let env: Vec<(CString, CString)> = self
+            .env
+            .iter()
+            .map(|(k, v)| {
+                Ok((
+                    CString::new(k.as_str()).map_err(invalid)?,
+                    CString::new(v.as_str()).map_err(invalid)?,
+                ))
+            })
+            .collect::<io::Result<_>>()?;
+
+        // The child reports a failed exec through this pipe, it is closed
+        // without anything written when exec succeeds
+        let mut fds = [0 as libc::c_int; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read_fd, write_fd) = (fds[0], fds[1]);
+
+        match unsafe { libc::fork() } {
+            -1 => {
+                let e = io::Error::last_os_error();
+                unsafe {
+                    libc::close(read_fd);
+                    libc::close(write_fd);
+                }
+                Err(e)
+            }
+            0 => unsafe {
+                libc::close(read_fd);
+                libc::setsid();
+                for (k, v) in env.iter() {
+                    libc::setenv(k.as_ptr(), v.as_ptr(), 1);
+                }
+                libc::execvp(argv_ptrs[0], argv_ptrs.as_ptr());
+
+                let errno = *libc::__errno_location();
+                libc::write(
+                    write_fd,
+                    &errno as *const libc::c_int as *const libc::c_void,
+                    std::mem::size_of::<libc::c_int>(),
+                );
+                libc::_exit(127);
+            },
+            _ => {
+                unsafe { libc::close(write_fd) };
+                let mut errno: libc::c_int = 0;
+                let read = unsafe {
+                    libc::read(
+                        read_fd,
+                        &mut errno as *mut libc::c_int as *mut libc::c_void,
+                        std::mem::size_of::<libc::c_int>(),
+                    )
+                };
+                unsafe { libc::close(read_fd) };
+                if read > 0 {
+                    Err(io::Error::from_raw_os_error(errno))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+/// Formats the same way spawn commands are parsed, without the command name
+impl fmt::Display for Spawn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let env = self.env.iter().map(|(k, v)| format!("{}={}", k, v));
+        let words: Vec<String> = env.chain(self.argv.iter().cloned()).collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leading_assignments_are_environment() {
+        let spawn = Spawn::from_words(&["LANG=C", "urxvt", "-e", "FOO=bar"], false).unwrap();
+        assert_eq!(spawn.env, vec![(String::from("LANG"), String::from("C"))]);
+        assert_eq!(spawn.argv, vec!["urxvt", "-e", "FOO=bar"]);
+        assert_eq!(spawn.to_string(), "LANG=C urxvt -e FOO=bar");
+
+        let shell = Spawn::from_words(&["dmenu_run", "|", "sh"], true).unwrap();
+        assert_eq!(shell.argv, vec!["dmenu_run | sh"]);
+
+        assert!(Spawn::from_words(&["LANG=C"], false).is_err());
+    }
+}
@@ -1,39 +1,49 @@
-use xcb;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use xcb::{randr, xinerama};
 use xcb_util::{ewmh, icccm, keysyms};
 
+use crate::backend::Backend;
+use crate::config::Color;
 use crate::key::*;
+use crate::rect::{Rect, MIN_SIZE};
 
 #[derive(Debug)]
 #[allow(non_snake_case)]
 struct InternedAtoms {
     WM_PROTOCOLS: xcb::Atom,
     WM_DELETE_WINDOW: xcb::Atom,
+    WORM_STATE: xcb::Atom,
 }
 
 #[allow(non_snake_case)]
 impl InternedAtoms {
     pub fn new(connection: &xcb::Connection) -> InternedAtoms {
-        let WM_PROTOCOLS = xcb::intern_atom(&connection, false, "WM_PROTOCOLS")
-            .get_reply()
-            .expect("Error creating InternedAtoms")
-            .atom();
-        let WM_DELETE_WINDOW = xcb::intern_atom(&connection, false, "WM_DELETE_WINDOW")
-            .get_reply()
-            .expect("Error creating InternedAtoms")
-            .atom();
+        let intern = |name: &str| {
+            xcb::intern_atom(connection, false, name)
+                .get_reply()
+                .expect("Error creating InternedAtoms")
+                .atom()
+        };
         InternedAtoms {
-            WM_PROTOCOLS,
-            WM_DELETE_WINDOW,
+            WM_PROTOCOLS: intern("WM_PROTOCOLS"),
+            WM_DELETE_WINDOW: intern("WM_DELETE_WINDOW"),
+            WORM_STATE: intern("_WORM_STATE"),
         }
     }
 }
 
+/// Id of an X window, usable as a key without carrying geometry around
+pub type WindowId = xcb::Window;
+
 /// Wrapping xcb::Window to not leak dependency
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Window {
     pub window: xcb::Window,
-    pub x: u32,
-    pub y: u32,
+    pub x: i32,
+    pub y: i32,
     pub width: u32,
     pub height: u32,
 }
@@ -42,7 +52,7 @@ impl Window {
     pub fn new(connection: &Connection, window: xcb::Window) -> Window {
         let geo = connection.get_window_geometry(window);
         Window {
-            window: window,
+            window,
             x: geo.0,
             y: geo.1,
             width: geo.2,
@@ -53,34 +63,268 @@ impl Window {
     pub fn as_xcb_window(&self) -> xcb::Window {
         self.window
     }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    pub fn set_rect(&mut self, rect: Rect) {
+        self.x = rect.x;
+        self.y = rect.y;
+        self.width = rect.width;
+        self.height = rect.height;
+    }
 }
 
 // TODO: Check if last 3 will ever be needed
 #[derive(Debug, Default)]
 pub struct WindowChanges {
-    pub x: u32,
-    pub y: u32,
+    pub x: i32,
+    pub y: i32,
     pub width: u32,
     pub height: u32,
     pub border_width: u32,
     pub sibling: u32,
     pub stack_mode: u32,
+    /// Whether x and y were asked for, rather than being the current position
+    pub moved: bool,
+}
+
+impl From<Rect> for WindowChanges {
+    fn from(rect: Rect) -> WindowChanges {
+        WindowChanges {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            ..Default::default()
+        }
+    }
+}
+
+/// Size constraints from WM_NORMAL_HINTS, unset fields are None
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SizeHints {
+    pub min: Option<(u32, u32)>,
+    pub max: Option<(u32, u32)>,
+    pub base: Option<(u32, u32)>,
+    pub increment: Option<(u32, u32)>,
+    /// Minimum and maximum aspect ratio as width / height
+    pub aspect: Option<(f32, f32)>,
+}
+
+impl SizeHints {
+    /// Whether the window only comes in one size
+    pub fn is_fixed(&self) -> bool {
+        self.min.is_some() && self.min == self.max
+    }
+
+    /// The size closest to width by height that the hints allow, worked out
+    /// as ICCCM 4.1.2.3 describes
+    pub fn constrain(&self, width: u32, height: u32) -> (u32, u32) {
+        let (min_width, min_height) = self.min.or(self.base).unwrap_or((0, 0));
+        let (base_width, base_height) = self.base.or(self.min).unwrap_or((0, 0));
+        // Aspect ratios leave out the base size, unless it doubles as the
+        // minimum size
+        let base_is_min = self.base.is_none() || self.min.is_none() || self.base == self.min;
+        let (mut width, mut height) = (width as f32, height as f32);
+        if !base_is_min {
+            width -= base_width as f32;
+            height -= base_height as f32;
+        }
+        if let Some((min_aspect, max_aspect)) = self.aspect {
+            if height > 0.0 && width / height > max_aspect {
+                width = (height * max_aspect).round();
+            } else if height > 0.0 && width / height < min_aspect && min_aspect > 0.0 {
+                height = (width / min_aspect).round();
+            }
+        }
+        if base_is_min {
+            width -= base_width as f32;
+            height -= base_height as f32;
+        }
+        let (mut width, mut height) = (width.max(0.0) as u32, height.max(0.0) as u32);
+
+        if let Some((width_increment, height_increment)) = self.increment {
+            if width_increment > 0 {
+                width -= width % width_increment;
+            }
+            if height_increment > 0 {
+                height -= height % height_increment;
+            }
+        }
+        width = width.saturating_add(base_width).max(min_width);
+        height = height.saturating_add(base_height).max(min_height);
+        if let Some((max_width, max_height)) = self.max {
+            if max_width > 0 {
+                width = width.min(max_width);
+            }
+            if max_height > 0 {
+                height = height.min(max_height);
+            }
+        }
+        (width.max(MIN_SIZE), height.max(MIN_SIZE))
+    }
+}
+
+/// Window properties the WM keeps track of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Property {
+    Name,
+    Class,
+    NormalHints,
+    Hints,
+    TransientFor,
+    Other,
+}
+
+/// Tells apart pointer crossings caused by the WM moving windows from those
+/// caused by the pointer moving. Requests that move or map windows open a
+/// span of sequence numbers, which a fence sent at the next flush closes.
+/// Events carry the sequence of the last request handled before them
+#[derive(Debug, Default)]
+pub struct RelayoutFilter {
+    span: Cell<Option<(u16, Option<u16>)>>,
+}
+
+impl RelayoutFilter {
+    /// Note a request that may move or map a window under the pointer
+    pub fn note(&self, sequence: u16) {
+        let start = match self.span.get() {
+            Some((start, _)) => start,
+            None => sequence,
+        };
+        self.span.set(Some((start, None)));
+    }
+
+    /// Whether requests were noted since the last fence
+    pub fn needs_fence(&self) -> bool {
+        matches!(self.span.get(), Some((_, None)))
+    }
+
+    /// Close the span at the request of sequence, sent after every request
+    /// noted
+    pub fn fence(&self, sequence: u16) {
+        if let Some((start, None)) = self.span.get() {
+            self.span.set(Some((start, Some(sequence))));
+        }
+    }
+
+    /// Whether an event of sequence came from requests that moved windows,
+    /// forgetting about them once events are past the fence
+    pub fn caused_by_relayout(&self, sequence: u16) -> bool {
+        let (start, fence) = match self.span.get() {
+            Some(r) => r,
+            None => return false,
+        };
+        // Sequence numbers wrap around, anything well behind is older
+        let offset = sequence.wrapping_sub(start);
+        if offset > u16::MAX / 2 {
+            return false;
+        }
+        match fence {
+            Some(fence) if offset >= fence.wrapping_sub(start) => {
+                self.span.set(None);
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Pointer shapes shown while a window is dragged
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cursor {
+    Move,
+    Resize,
+}
+
+/// Requests other clients make of the WM through EWMH client messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientRequest {
+    /// Switch to the desktop at index
+    CurrentDesktop(usize),
+    /// Show and focus the window
+    Activate,
+    Close,
+    /// Move the window to the desktop at index
+    WindowDesktop(usize),
+    /// Enter or leave fullscreen, None toggles
+    Fullscreen(Option<bool>),
 }
 
 #[derive(Debug)]
 pub enum XEvent {
     ConfigureRequest(Window, WindowChanges),
+    ConfigureNotify(Window),
     MapRequest(Window),
     UnmapNotify(Window),
     DestroyNotify(Window),
+    PropertyNotify(Window, Property),
     KeyPress(Key),
+    /// A frame needs its title bar redrawn
+    Expose(Window),
+    /// Monitors were added, removed or changed resolution
+    ScreenChange,
+    ClientMessage(Window, ClientRequest),
+    /// A grabbed button went down over a frame with the given modifiers
+    /// held, and the pointer at the given position on the root
+    ButtonPress(Window, u8, ModMask, (i32, i32)),
+    /// The pointer crossed into a window
+    EnterNotify(Window),
+    /// The pointer moved to a position on the root during a pointer grab
+    MotionNotify((i32, i32)),
+    ButtonRelease,
 }
 
+/// Name the WM gives itself in _NET_WM_NAME
+const WM_NAME: &str = "worm";
+
+/// _NET_WM_DESKTOP of windows shown on every desktop
+const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+/// Actions of _NET_WM_STATE requests, anything else toggles
+const STATE_REMOVE: u32 = 0;
+const STATE_ADD: u32 = 1;
+
+/// Core font title bars are drawn with
+const TITLE_FONT: &str = "fixed";
+
+/// Modifier bits of key and button event states, leaving out the buttons
+const KEY_MODIFIERS: ModMask = 0xff;
+
+/// Caps Lock and Num Lock, which should not change what a key or click does
+const LOCK_MODIFIERS: ModMask = xcb::MOD_MASK_LOCK | xcb::MOD_MASK_2;
+
+/// Every combination of locks that may be on while a binding is used
+const LOCK_COMBINATIONS: [ModMask; 4] = [0, xcb::MOD_MASK_LOCK, xcb::MOD_MASK_2, LOCK_MODIFIERS];
+
+/// Font the standard X cursors come from, and the glyphs used from it
+const CURSOR_FONT: &str = "cursor";
+const CURSOR_FLEUR: u16 = 52;
+const CURSOR_BOTTOM_RIGHT_CORNER: u16 = 14;
+
 pub struct Connection {
     connection: ewmh::Connection,
     root_window: Window,
     root_index: i32,
     atoms: InternedAtoms,
+    /// Unmaps requested by the WM that have not been reported back yet, so
+    /// that hiding a window is not mistaken for the client withdrawing it
+    pending_unmaps: RefCell<HashMap<xcb::Window, u32>>,
+    /// Frame windows created by the WM, only the WM ever unmaps them
+    frames: RefCell<HashSet<xcb::Window>>,
+    title_gc: xcb::Gcontext,
+    title_ascent: i16,
+    title_descent: i16,
+    /// First event number of RandR, None if the server lacks it
+    randr_event_base: Option<u8>,
+    /// Window named in _NET_SUPPORTING_WM_CHECK
+    check_window: xcb::Window,
+    move_cursor: xcb::Cursor,
+    resize_cursor: xcb::Cursor,
+    /// Pointer crossings caused by moving windows rather than the user
+    relayout: RelayoutFilter,
 }
 
 impl Connection {
@@ -106,121 +350,229 @@ impl Connection {
 
         let root_window = Window {
             window: root_window,
-            x: root_geo.x() as u32,
-            y: root_geo.y() as u32,
+            x: root_geo.x() as i32,
+            y: root_geo.y() as i32,
             width: root_geo.width() as u32,
             height: root_geo.height() as u32,
         };
 
         let atoms = InternedAtoms::new(&connection);
 
+        let font = connection.generate_id();
+        xcb::open_font(&connection, font, TITLE_FONT);
+        let title_gc = connection.generate_id();
+        xcb::create_gc(
+            &connection,
+            title_gc,
+            root_window.as_xcb_window(),
+            &[(xcb::GC_FONT, font)],
+        );
+        let font_info = xcb::query_font(&connection, font)
+            .get_reply()
+            .expect("Could not open title font");
+
+        let cursor_font = connection.generate_id();
+        xcb::open_font(&connection, cursor_font, CURSOR_FONT);
+        let cursor = |glyph: u16| {
+            let cursor = connection.generate_id();
+            xcb::create_glyph_cursor(
+                &connection,
+                cursor,
+                cursor_font,
+                cursor_font,
+                glyph,
+                glyph + 1,
+                0,
+                0,
+                0,
+                0xffff,
+                0xffff,
+                0xffff,
+            );
+            cursor
+        };
+        let move_cursor = cursor(CURSOR_FLEUR);
+        let resize_cursor = cursor(CURSOR_BOTTOM_RIGHT_CORNER);
+        xcb::close_font(&connection, cursor_font);
+
+        let randr_event_base = connection
+            .get_extension_data(randr::id())
+            .filter(|e| e.present())
+            .map(|e| e.first_event());
+
+        // Bars and pagers find the WM through a window it owns
+        let check_window = connection.generate_id();
+        xcb::create_window(
+            &connection,
+            xcb::COPY_FROM_PARENT as u8,
+            check_window,
+            root_window.as_xcb_window(),
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,
+            xcb::COPY_FROM_PARENT,
+            &[],
+        );
+
         Connection {
             connection,
             root_window,
             root_index,
             atoms,
+            pending_unmaps: RefCell::new(HashMap::new()),
+            frames: RefCell::new(HashSet::new()),
+            title_gc,
+            title_ascent: font_info.font_ascent(),
+            title_descent: font_info.font_descent(),
+            randr_event_base,
+            check_window,
+            move_cursor,
+            resize_cursor,
+            relayout: RelayoutFilter::default(),
         }
     }
 
-    pub fn setup(&self, keys: &KeyMap) {
-        // register for substructure redirect/notify
-        let values = [(
-            xcb::CW_EVENT_MASK,
-            xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY | xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT,
-        )];
+    /// Publish which window manager is running and the EWMH hints it
+    /// supports
+    fn announce_wm(&self) {
+        let c = &self.connection;
+        let root = self.root_window.as_xcb_window();
+        ewmh::set_supporting_wm_check(c, root, self.check_window);
+        ewmh::set_supporting_wm_check(c, self.check_window, self.check_window);
+        ewmh::set_wm_name(c, self.check_window, WM_NAME);
 
-        xcb::change_window_attributes_checked(
+        let supported = [
+            c.SUPPORTED(),
+            c.SUPPORTING_WM_CHECK(),
+            c.WM_NAME(),
+            c.CLIENT_LIST(),
+            c.CLIENT_LIST_STACKING(),
+            c.NUMBER_OF_DESKTOPS(),
+            c.CURRENT_DESKTOP(),
+            c.DESKTOP_NAMES(),
+            c.WM_DESKTOP(),
+            c.ACTIVE_WINDOW(),
+            c.CLOSE_WINDOW(),
+            c.FRAME_EXTENTS(),
+            c.WM_STATE(),
+            c.WM_STATE_FULLSCREEN(),
+        ];
+        ewmh::set_supported(c, self.root_index, &supported);
+    }
+
+    fn get_randr_monitors(&self) -> Vec<Rect> {
+        let resources = match randr::get_screen_resources_current(
             &self.connection,
             self.root_window.as_xcb_window(),
-            &values,
         )
-        .request_check()
-        .expect("Could not register for substructure redirect/notify");
-
-        self.grab_keys(&self.root_window, keys);
-    }
-
-    pub fn get_existing_windows(&self) -> Vec<Window> {
-        // frame existing windows
-        xcb::grab_server(&self.connection);
-
-        let existing_windows: Vec<_> =
-            xcb::query_tree(&self.connection, self.root_window.as_xcb_window())
-                .get_reply()
-                .expect("Could not query existing windows")
-                .children()
-                .iter()
-                .map(|w| Window::new(&self, *w))
-                .collect();
-
-        xcb::ungrab_server(&self.connection);
-
-        existing_windows
+        .get_reply()
+        {
+            Ok(r) => r,
+            Err(_) => return Vec::new(),
+        };
+        resources
+            .crtcs()
+            .iter()
+            .filter_map(|crtc| {
+                let info =
+                    randr::get_crtc_info(&self.connection, *crtc, resources.config_timestamp())
+                        .get_reply()
+                        .ok()?;
+                // Crtcs without a mode are not driving any output
+                if info.mode() == xcb::NONE || info.width() == 0 || info.height() == 0 {
+                    return None;
+                }
+                Some(Rect::new(
+                    info.x() as i32,
+                    info.y() as i32,
+                    info.width() as u32,
+                    info.height() as u32,
+                ))
+            })
+            .collect()
     }
 
-    pub fn root_window(&self) -> Window {
-        Window::new(&self, self.root_window.as_xcb_window())
+    fn get_xinerama_monitors(&self) -> Vec<Rect> {
+        let active = xinerama::is_active(&self.connection)
+            .get_reply()
+            .map(|r| r.state() != 0)
+            .unwrap_or(false);
+        if !active {
+            return Vec::new();
+        }
+        match xinerama::query_screens(&self.connection).get_reply() {
+            Ok(screens) => screens
+                .screen_info()
+                .map(|s| {
+                    Rect::new(
+                        s.x_org() as i32,
+                        s.y_org() as i32,
+                        s.width() as u32,
+                        s.height() as u32,
+                    )
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
     }
 
-    pub fn flush(&self) {
-        self.connection.flush();
+    /// Requests of missing extensions close the connection, so check first
+    fn has_extension(&self, extension: &mut xcb::Extension) -> bool {
+        self.connection
+            .get_extension_data(extension)
+            .is_some_and(|e| e.present())
     }
 
-    pub fn wait_for_event(&self) -> Option<XEvent> {
-        let e = self
-            .connection
-            .wait_for_event()
-            .expect("Error receiving event");
-        unsafe {
-            match e.response_type() {
-                xcb::CONFIGURE_REQUEST => self.configure_request(xcb::cast_event(&e)),
-                xcb::MAP_REQUEST => self.map_request(xcb::cast_event(&e)),
-                xcb::UNMAP_NOTIFY => self.unmap_notify(xcb::cast_event(&e)),
-                xcb::KEY_PRESS => self.key_press(xcb::cast_event(&e)),
-                xcb::DESTROY_NOTIFY => self.destroy_notify(xcb::cast_event(&e)),
-                _ => None,
-            }
-        }
+    /// Note a request that may move or map a window under the pointer
+    fn note_relayout(&self, cookie: xcb::VoidCookie) {
+        self.relayout.note(cookie.cookie.sequence as u16);
     }
 
     fn configure_request(&self, event: &xcb::ConfigureRequestEvent) -> Option<XEvent> {
+        let position = (xcb::CONFIG_WINDOW_X | xcb::CONFIG_WINDOW_Y) as u16;
         Some(XEvent::ConfigureRequest(
-            Window::new(&self, event.window()),
+            Window::new(self, event.window()),
             WindowChanges {
-                x: event.x() as u32,
-                y: event.y() as u32,
+                x: event.x() as i32,
+                y: event.y() as i32,
                 width: event.width() as u32,
                 height: event.height() as u32,
                 border_width: event.border_width() as u32,
-                sibling: event.sibling() as u32,
+                sibling: event.sibling(),
                 stack_mode: event.stack_mode() as u32,
+                moved: event.value_mask() & position != 0,
             },
         ))
     }
 
-    pub fn configure_window(&self, window: &Window, window_changes: &WindowChanges) {
-        let value_list = vec![
-            (xcb::CONFIG_WINDOW_X as u16, window_changes.x),
-            (xcb::CONFIG_WINDOW_Y as u16, window_changes.y),
-            (xcb::CONFIG_WINDOW_WIDTH as u16, window_changes.width),
-            (xcb::CONFIG_WINDOW_HEIGHT as u16, window_changes.height),
-        ];
-
-        xcb::configure_window(&self.connection, window.as_xcb_window(), &value_list);
+    fn configure_notify(&self, event: &xcb::ConfigureNotifyEvent) -> Option<XEvent> {
+        // Tracked windows report their own changes, skip the root's copy
+        if event.event() != event.window() {
+            return None;
+        }
+        Some(XEvent::ConfigureNotify(Window {
+            window: event.window(),
+            x: event.x() as i32,
+            y: event.y() as i32,
+            width: event.width() as u32,
+            height: event.height() as u32,
+        }))
     }
 
     fn map_request(&self, event: &xcb::MapRequestEvent) -> Option<XEvent> {
-        Some(XEvent::MapRequest(Window::new(&self, event.window())))
-    }
-
-    pub fn map_window(&self, window: &Window) {
-        xcb::map_window(&self.connection, window.as_xcb_window());
+        Some(XEvent::MapRequest(Window::new(self, event.window())))
     }
 
     fn unmap_notify(&self, event: &xcb::UnmapNotifyEvent) -> Option<XEvent> {
-        let mut ret;
+        let ret;
         if event.event() == self.root_window.as_xcb_window() {
             ret = None;
+        } else if self.take_pending_unmap(event.window()) {
+            // Unmapped by the WM itself, the window is still managed
+            ret = None;
         } else {
             // Avoiding a looking for the window geo through Window cons
             let mut window = Window::default();
@@ -230,50 +582,369 @@ impl Connection {
         ret
     }
 
-    pub fn unmap_window(&self, window: &Window) {
-        xcb::unmap_window(&self.connection, window.as_xcb_window());
+    /// Note that the next unmap of the window is the WM's own doing
+    fn expect_unmap(&self, window: &Window) {
+        *self
+            .pending_unmaps
+            .borrow_mut()
+            .entry(window.as_xcb_window())
+            .or_insert(0) += 1;
+    }
+
+    /// Returns true if an unmap of the window was requested by the WM and
+    /// consumes it
+    fn take_pending_unmap(&self, window: xcb::Window) -> bool {
+        let mut pending_unmaps = self.pending_unmaps.borrow_mut();
+        match pending_unmaps.get_mut(&window) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                true
+            }
+            Some(_) => {
+                pending_unmaps.remove(&window);
+                true
+            }
+            None => false,
+        }
     }
 
     fn key_press(&self, event: &xcb::KeyPressEvent) -> Option<XEvent> {
         let key_symbols = keysyms::KeySymbols::new(&self.connection);
         let key = key_symbols.press_lookup_keysym(event, 0);
-        let modifier = u32::from(event.state());
+        let modifier = ModMask::from(event.state()) & KEY_MODIFIERS & !LOCK_MODIFIERS;
         let key = Key { modifier, key };
         Some(XEvent::KeyPress(key))
     }
 
     fn destroy_notify(&self, event: &xcb::DestroyNotifyEvent) -> Option<XEvent> {
-        let mut ret;
+        let ret;
         if event.event() == self.root_window.as_xcb_window() {
             ret = None;
         } else {
+            // The window is gone, any unmaps still expected for it never arrive
+            self.pending_unmaps.borrow_mut().remove(&event.window());
             // Avoiding a looking for the window geo through Window cons
             let mut window = Window::default();
             window.window = event.window();
-            ret = Some(XEvent::UnmapNotify(window));
+            ret = Some(XEvent::DestroyNotify(window));
         }
         ret
     }
 
-    pub fn grab_keys(&self, window: &Window, keys: &KeyMap) {
-        let key_symbols = keysyms::KeySymbols::new(&self.connection);
-        for key in keys.key_map.keys() {
-            xcb::grab_key(
+    fn property_notify(&self, event: &xcb::PropertyNotifyEvent) -> Option<XEvent> {
+        if event.window() == self.root_window.as_xcb_window() {
+            return None;
+        }
+
+        let atom = event.atom();
+        let property = if atom == xcb::ATOM_WM_NAME || atom == self.connection.WM_NAME() {
+            Property::Name
+        } else if atom == xcb::ATOM_WM_CLASS {
+            Property::Class
+        } else if atom == xcb::ATOM_WM_NORMAL_HINTS {
+            Property::NormalHints
+        } else if atom == xcb::ATOM_WM_HINTS {
+            Property::Hints
+        } else if atom == xcb::ATOM_WM_TRANSIENT_FOR {
+            Property::TransientFor
+        } else {
+            Property::Other
+        };
+
+        let mut window = Window::default();
+        window.window = event.window();
+        Some(XEvent::PropertyNotify(window, property))
+    }
+
+    fn expose(&self, event: &xcb::ExposeEvent) -> Option<XEvent> {
+        // Redraw once the last of a series of exposures arrives
+        if event.count() != 0 || !self.frames.borrow().contains(&event.window()) {
+            return None;
+        }
+        let mut window = Window::default();
+        window.window = event.window();
+        Some(XEvent::Expose(window))
+    }
+
+    fn client_message(&self, event: &xcb::ClientMessageEvent) -> Option<XEvent> {
+        let atom = event.type_();
+        let data = event.data().data32();
+        let argument = data[0];
+        let request = if atom == self.connection.CURRENT_DESKTOP() {
+            ClientRequest::CurrentDesktop(argument as usize)
+        } else if atom == self.connection.ACTIVE_WINDOW() {
+            ClientRequest::Activate
+        } else if atom == self.connection.CLOSE_WINDOW() {
+            ClientRequest::Close
+        } else if atom == self.connection.WM_DESKTOP() && argument != ALL_DESKTOPS {
+            ClientRequest::WindowDesktop(argument as usize)
+        } else if atom == self.connection.WM_STATE()
+            && data[1..3].contains(&self.connection.WM_STATE_FULLSCREEN())
+        {
+            ClientRequest::Fullscreen(match argument {
+                STATE_REMOVE => Some(false),
+                STATE_ADD => Some(true),
+                _ => None,
+            })
+        } else {
+            return None;
+        };
+
+        let mut window = Window::default();
+        window.window = event.window();
+        Some(XEvent::ClientMessage(window, request))
+    }
+
+    fn button_press(&self, event: &xcb::ButtonPressEvent) -> Option<XEvent> {
+        let window = Window {
+            window: event.event(),
+            ..Default::default()
+        };
+        let modifiers = ModMask::from(event.state()) & KEY_MODIFIERS & !LOCK_MODIFIERS;
+        let position = (event.root_x() as i32, event.root_y() as i32);
+        Some(XEvent::ButtonPress(
+            window,
+            event.detail(),
+            modifiers,
+            position,
+        ))
+    }
+
+    fn enter_notify(&self, event: &xcb::EnterNotifyEvent) -> Option<XEvent> {
+        // Grabs and moving between a window and its children are not crossings
+        if event.mode() != xcb::NOTIFY_MODE_NORMAL as u8
+            || event.detail() == xcb::NOTIFY_DETAIL_INFERIOR as u8
+        {
+            return None;
+        }
+        let window = Window {
+            window: event.event(),
+            ..Default::default()
+        };
+        Some(XEvent::EnterNotify(window))
+    }
+
+    fn motion_notify(&self, event: &xcb::MotionNotifyEvent) -> Option<XEvent> {
+        Some(XEvent::MotionNotify((
+            event.root_x() as i32,
+            event.root_y() as i32,
+        )))
+    }
+
+    /// WM_PROTOCOLS of the window, empty if it has none or is already gone
+    fn get_wm_protocols(&self, window: &Window) -> Vec<xcb::Atom> {
+        icccm::get_wm_protocols(
+            &self.connection,
+            window.as_xcb_window(),
+            self.atoms.WM_PROTOCOLS,
+        )
+        .get_reply()
+        .map(|protocols| Vec::from(protocols.atoms()))
+        .unwrap_or_default()
+    }
+
+    /// function to find xcb::idow geometry as (x, y, width, height)
+    pub fn get_window_geometry(&self, window: xcb::Window) -> (i32, i32, u32, u32) {
+        let geo = xcb::get_geometry(&self.connection, window)
+            .get_reply()
+            .unwrap_or_else(|_| panic!("Could not get window geometry for window: {}", window));
+        (
+            geo.x() as i32,
+            geo.y() as i32,
+            geo.width() as u32,
+            geo.height() as u32,
+        )
+    }
+}
+
+impl Backend for Connection {
+    fn setup(&self, keys: &KeyMap) {
+        // register for substructure redirect/notify
+        let values = [(
+            xcb::CW_EVENT_MASK,
+            xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY | xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT,
+        )];
+
+        xcb::change_window_attributes_checked(
+            &self.connection,
+            self.root_window.as_xcb_window(),
+            &values,
+        )
+        .request_check()
+        .expect("Could not register for substructure redirect/notify");
+
+        if self.randr_event_base.is_some() {
+            let mask = randr::NOTIFY_MASK_SCREEN_CHANGE
+                | randr::NOTIFY_MASK_CRTC_CHANGE
+                | randr::NOTIFY_MASK_OUTPUT_CHANGE;
+            randr::select_input(
                 &self.connection,
-                false,
-                window.as_xcb_window(),
-                key.modifier as u16,
-                key_symbols
-                    .get_keycode(key.key)
-                    .next()
-                    .expect("Could not resolve keysym"),
-                xcb::GRAB_MODE_ASYNC as u8,
-                xcb::GRAB_MODE_ASYNC as u8,
+                self.root_window.as_xcb_window(),
+                mask as u16,
             );
         }
+
+        self.grab_keys(&self.root_window, keys);
+        self.announce_wm();
+    }
+
+    fn get_existing_windows(&self) -> Vec<(Window, bool)> {
+        // frame existing windows
+        xcb::grab_server(&self.connection);
+
+        let existing_windows: Vec<_> =
+            xcb::query_tree(&self.connection, self.root_window.as_xcb_window())
+                .get_reply()
+                .expect("Could not query existing windows")
+                .children()
+                .iter()
+                .filter_map(|w| {
+                    let attributes = xcb::get_window_attributes(&self.connection, *w)
+                        .get_reply()
+                        .ok()?;
+                    if attributes.override_redirect() {
+                        return None;
+                    }
+                    let viewable = attributes.map_state() == xcb::MAP_STATE_VIEWABLE as u8;
+                    Some((Window::new(self, *w), viewable))
+                })
+                .collect();
+
+        xcb::ungrab_server(&self.connection);
+
+        existing_windows
+    }
+
+    fn get_monitors(&self) -> Vec<Rect> {
+        let mut monitors = if self.randr_event_base.is_some() {
+            self.get_randr_monitors()
+        } else if self.has_extension(xinerama::id()) {
+            self.get_xinerama_monitors()
+        } else {
+            Vec::new()
+        };
+        if monitors.is_empty() {
+            // The root is resized along with the screen, ask for its size
+            let (x, y, width, height) = self.get_window_geometry(self.root_window.as_xcb_window());
+            monitors.push(Rect::new(x, y, width, height));
+        }
+        monitors.sort_by_key(|m| (m.x, m.y, m.width, m.height));
+        monitors.dedup();
+        monitors
+    }
+
+    fn release(&self) {
+        let values = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)];
+        xcb::change_window_attributes(&self.connection, self.root_window.as_xcb_window(), &values);
+        self.ungrab_keys(&self.root_window);
+        xcb::delete_property(
+            &self.connection,
+            self.root_window.as_xcb_window(),
+            self.connection.SUPPORTING_WM_CHECK(),
+        );
+        xcb::destroy_window(&self.connection, self.check_window);
+        xcb::set_input_focus(
+            &self.connection,
+            xcb::INPUT_FOCUS_POINTER_ROOT as u8,
+            xcb::INPUT_FOCUS_POINTER_ROOT,
+            xcb::CURRENT_TIME,
+        );
+        self.flush();
+    }
+
+    fn set_saved_state(&self, state: &str) {
+        xcb::change_property(
+            &self.connection,
+            xcb::PROP_MODE_REPLACE as u8,
+            self.root_window.as_xcb_window(),
+            self.atoms.WORM_STATE,
+            xcb::ATOM_STRING,
+            8,
+            state.as_bytes(),
+        );
+    }
+
+    fn take_saved_state(&self) -> Option<String> {
+        let reply = xcb::get_property(
+            &self.connection,
+            true,
+            self.root_window.as_xcb_window(),
+            self.atoms.WORM_STATE,
+            xcb::ATOM_STRING,
+            0,
+            u32::MAX / 4,
+        )
+        .get_reply()
+        .ok()?;
+        if reply.value_len() == 0 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(reply.value::<u8>()).into_owned())
     }
 
-    pub fn register_window(&self, window: &Window) {
+    fn root_window(&self) -> Window {
+        Window::new(self, self.root_window.as_xcb_window())
+    }
+
+    fn wait_for_event(&self, wakeup: RawFd) -> Option<XEvent> {
+        let e = loop {
+            if let Some(e) = self.connection.poll_for_event() {
+                break e;
+            }
+            self.connection.has_error().expect("Error receiving event");
+
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.connection.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: wakeup,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            unsafe {
+                libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1);
+            }
+            if fds[1].revents != 0 {
+                return None;
+            }
+        };
+        let moved_by_wm = self
+            .relayout
+            .caused_by_relayout(unsafe { (*e.ptr).sequence });
+        if let Some(base) = self.randr_event_base {
+            // Extension events are numbered from where the server put them
+            let response_type = e.response_type() & !0x80;
+            if response_type == base + randr::SCREEN_CHANGE_NOTIFY
+                || response_type == base + randr::NOTIFY
+            {
+                return Some(XEvent::ScreenChange);
+            }
+        }
+        unsafe {
+            match e.response_type() {
+                xcb::CONFIGURE_REQUEST => self.configure_request(xcb::cast_event(&e)),
+                xcb::MAP_REQUEST => self.map_request(xcb::cast_event(&e)),
+                xcb::UNMAP_NOTIFY => self.unmap_notify(xcb::cast_event(&e)),
+                xcb::KEY_PRESS => self.key_press(xcb::cast_event(&e)),
+                xcb::DESTROY_NOTIFY => self.destroy_notify(xcb::cast_event(&e)),
+                xcb::CONFIGURE_NOTIFY => self.configure_notify(xcb::cast_event(&e)),
+                xcb::PROPERTY_NOTIFY => self.property_notify(xcb::cast_event(&e)),
+                xcb::EXPOSE => self.expose(xcb::cast_event(&e)),
+                xcb::CLIENT_MESSAGE => self.client_message(xcb::cast_event(&e)),
+                xcb::BUTTON_PRESS => self.button_press(xcb::cast_event(&e)),
+                xcb::MOTION_NOTIFY => self.motion_notify(xcb::cast_event(&e)),
+                xcb::BUTTON_RELEASE => Some(XEvent::ButtonRelease),
+                xcb::ENTER_NOTIFY if !moved_by_wm => self.enter_notify(xcb::cast_event(&e)),
+                _ => None,
+            }
+        }
+    }
+
+    fn register_window(&self, window: &Window) {
         let values = [(
             xcb::CW_EVENT_MASK,
             xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY | xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT,
@@ -284,40 +955,313 @@ impl Connection {
             .expect("Could not register for substructure redirect/notify");
     }
 
-    pub fn track_window_events(&self, window: &Window) {
+    fn grab_buttons(&self, window: &Window, modifier: ModMask, click_to_focus: bool) {
+        xcb::ungrab_button(
+            &self.connection,
+            xcb::BUTTON_INDEX_ANY as u8,
+            window.as_xcb_window(),
+            xcb::MOD_MASK_ANY as u16,
+        );
+        // Grabs for particular modifiers take precedence over this one
+        if click_to_focus {
+            xcb::grab_button(
+                &self.connection,
+                false,
+                window.as_xcb_window(),
+                xcb::EVENT_MASK_BUTTON_PRESS as u16,
+                xcb::GRAB_MODE_SYNC as u8,
+                xcb::GRAB_MODE_ASYNC as u8,
+                xcb::NONE,
+                xcb::NONE,
+                xcb::BUTTON_INDEX_ANY as u8,
+                xcb::MOD_MASK_ANY as u16,
+            );
+        }
+        // Grab again for every combination of locks that may be on
+        for button in [xcb::BUTTON_INDEX_1, xcb::BUTTON_INDEX_3].iter() {
+            for locks in LOCK_COMBINATIONS.iter() {
+                xcb::grab_button(
+                    &self.connection,
+                    false,
+                    window.as_xcb_window(),
+                    xcb::EVENT_MASK_BUTTON_PRESS as u16,
+                    xcb::GRAB_MODE_ASYNC as u8,
+                    xcb::GRAB_MODE_ASYNC as u8,
+                    xcb::NONE,
+                    xcb::NONE,
+                    *button as u8,
+                    (modifier | locks) as u16,
+                );
+            }
+        }
+    }
+
+    fn grab_pointer(&self, cursor: Cursor) -> bool {
+        let cursor = match cursor {
+            Cursor::Move => self.move_cursor,
+            Cursor::Resize => self.resize_cursor,
+        };
+        let mask = xcb::EVENT_MASK_BUTTON_RELEASE | xcb::EVENT_MASK_POINTER_MOTION;
+        let reply = xcb::grab_pointer(
+            &self.connection,
+            false,
+            self.root_window.as_xcb_window(),
+            mask as u16,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::NONE,
+            cursor,
+            xcb::CURRENT_TIME,
+        )
+        .get_reply();
+        match reply {
+            Ok(reply) => reply.status() == xcb::GRAB_STATUS_SUCCESS as u8,
+            Err(_) => false,
+        }
+    }
+
+    fn ungrab_pointer(&self) {
+        xcb::ungrab_pointer(&self.connection, xcb::CURRENT_TIME);
+    }
+
+    fn replay_pointer(&self) {
+        xcb::allow_events(
+            &self.connection,
+            xcb::ALLOW_REPLAY_POINTER as u8,
+            xcb::CURRENT_TIME,
+        );
+    }
+
+    fn flush(&self) {
+        // Crossings up to this request may come from moving windows
+        if self.relayout.needs_fence() {
+            let fence = xcb::no_operation(&self.connection);
+            self.relayout.fence(fence.cookie.sequence as u16);
+        }
+        self.connection.flush();
+    }
+
+    fn map_window(&self, window: &Window) {
+        self.note_relayout(xcb::map_window(&self.connection, window.as_xcb_window()));
+    }
+
+    fn unmap_window(&self, window: &Window) {
+        if !self.frames.borrow().contains(&window.as_xcb_window()) {
+            self.expect_unmap(window);
+        }
+        self.note_relayout(xcb::unmap_window(&self.connection, window.as_xcb_window()));
+    }
+
+    fn create_frame(&self, window: &Window, title_height: u32) -> Window {
+        let frame = Window {
+            window: self.connection.generate_id(),
+            height: window.height.saturating_add(title_height),
+            ..*window
+        };
         let values = [(
             xcb::CW_EVENT_MASK,
-            xcb::EVENT_MASK_ENTER_WINDOW | xcb::EVENT_MASK_STRUCTURE_NOTIFY,
+            xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT | xcb::EVENT_MASK_EXPOSURE,
         )];
+        xcb::create_window(
+            &self.connection,
+            xcb::COPY_FROM_PARENT as u8,
+            frame.as_xcb_window(),
+            self.root_window.as_xcb_window(),
+            frame.x as i16,
+            frame.y as i16,
+            frame.width.max(1) as u16,
+            frame.height.max(1) as u16,
+            0,
+            xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+            xcb::COPY_FROM_PARENT,
+            &values,
+        );
+        self.frames.borrow_mut().insert(frame.as_xcb_window());
 
-        xcb::change_window_attributes_checked(&self.connection, window.as_xcb_window(), &values)
-            .request_check()
-            .expect("Could not track window events");
+        // Reparenting a mapped window unmaps it on the way
+        if self.is_viewable(window) {
+            self.expect_unmap(window);
+        }
+        // The server puts the client back on the root if the WM dies
+        xcb::change_save_set(
+            &self.connection,
+            xcb::SET_MODE_INSERT as u8,
+            window.as_xcb_window(),
+        );
+        xcb::reparent_window(
+            &self.connection,
+            window.as_xcb_window(),
+            frame.as_xcb_window(),
+            0,
+            title_height as i16,
+        );
+        xcb::map_window(&self.connection, window.as_xcb_window());
+        frame
     }
 
-    pub fn stop_window_events(&self, window: &Window) {
-        let values = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)];
-        xcb::change_window_attributes_checked(&self.connection, window.as_xcb_window(), &values)
-            .request_check()
-            .expect("Could not stop window events");
+    fn destroy_frame(&self, frame: &Window, window: &Window) {
+        xcb::change_save_set(
+            &self.connection,
+            xcb::SET_MODE_DELETE as u8,
+            window.as_xcb_window(),
+        );
+        xcb::reparent_window(
+            &self.connection,
+            window.as_xcb_window(),
+            self.root_window.as_xcb_window(),
+            frame.x as i16,
+            frame.y as i16,
+        );
+        self.note_relayout(xcb::destroy_window(&self.connection, frame.as_xcb_window()));
+        self.frames.borrow_mut().remove(&frame.as_xcb_window());
+    }
+
+    fn set_frame_extents(&self, window: &Window, border_width: u32, title_height: u32) {
+        ewmh::set_frame_extents(
+            &self.connection,
+            window.as_xcb_window(),
+            border_width,
+            border_width,
+            border_width + title_height,
+            border_width,
+        );
     }
 
-    pub fn focus_window(&self, window: Window) {
-        xcb::set_input_focus_checked(
+    fn draw_title(
+        &self,
+        frame: &Window,
+        title: &str,
+        height: u32,
+        foreground: Color,
+        background: Color,
+    ) {
+        let gc = self.title_gc;
+        xcb::change_gc(&self.connection, gc, &[(xcb::GC_FOREGROUND, background)]);
+        let bar = xcb::Rectangle::new(0, 0, frame.width as u16, height as u16);
+        xcb::poly_fill_rectangle(&self.connection, frame.as_xcb_window(), gc, &[bar]);
+
+        // Core fonts only cover latin-1, anything else is replaced
+        let text: String = title
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && !c.is_control() {
+                    c
+                } else {
+                    '?'
+                }
+            })
+            .take(255)
+            .collect();
+        let font_height = self.title_ascent + self.title_descent;
+        let baseline = (height as i16 - font_height) / 2 + self.title_ascent;
+        xcb::change_gc(
+            &self.connection,
+            gc,
+            &[
+                (xcb::GC_FOREGROUND, foreground),
+                (xcb::GC_BACKGROUND, background),
+            ],
+        );
+        xcb::image_text_8(
+            &self.connection,
+            frame.as_xcb_window(),
+            gc,
+            font_height / 2,
+            baseline,
+            &text,
+        );
+    }
+
+    fn configure_window(&self, window: &Window, window_changes: &WindowChanges) {
+        let value_list = vec![
+            // Negative positions go over the wire sign extended
+            (xcb::CONFIG_WINDOW_X as u16, window_changes.x as u32),
+            (xcb::CONFIG_WINDOW_Y as u16, window_changes.y as u32),
+            (xcb::CONFIG_WINDOW_WIDTH as u16, window_changes.width),
+            (xcb::CONFIG_WINDOW_HEIGHT as u16, window_changes.height),
+        ];
+
+        self.note_relayout(xcb::configure_window(
+            &self.connection,
+            window.as_xcb_window(),
+            &value_list,
+        ));
+    }
+
+    fn set_client_list(&self, windows: &[WindowId], stacking: &[WindowId]) {
+        ewmh::set_client_list(&self.connection, self.root_index, windows);
+        ewmh::set_client_list_stacking(&self.connection, self.root_index, stacking);
+    }
+
+    fn set_desktop_names(&self, names: &[String]) {
+        ewmh::set_number_of_desktops(&self.connection, self.root_index, names.len() as u32);
+        ewmh::set_desktop_names(
+            &self.connection,
+            self.root_index,
+            names.iter().map(|n| n.as_str()),
+        );
+    }
+
+    fn set_current_desktop(&self, desktop: usize) {
+        ewmh::set_current_desktop(&self.connection, self.root_index, desktop as u32);
+    }
+
+    fn set_window_desktop(&self, window: &Window, desktop: usize) {
+        ewmh::set_wm_desktop(&self.connection, window.as_xcb_window(), desktop as u32);
+    }
+
+    fn set_border_width(&self, window: &Window, width: u32) {
+        let value_list = [(xcb::CONFIG_WINDOW_BORDER_WIDTH as u16, width)];
+        self.note_relayout(xcb::configure_window(
+            &self.connection,
+            window.as_xcb_window(),
+            &value_list,
+        ));
+    }
+
+    fn raise_window(&self, window: &Window) {
+        let value_list = [(xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE)];
+        self.note_relayout(xcb::configure_window(
+            &self.connection,
+            window.as_xcb_window(),
+            &value_list,
+        ));
+    }
+
+    fn set_fullscreen_state(&self, window: &Window, fullscreen: bool) {
+        let states = if fullscreen {
+            vec![self.connection.WM_STATE_FULLSCREEN()]
+        } else {
+            Vec::new()
+        };
+        ewmh::set_wm_state(&self.connection, window.as_xcb_window(), &states);
+    }
+
+    fn set_border_color(&self, window: &Window, color: Color) {
+        let values = [(xcb::CW_BORDER_PIXEL, color)];
+        xcb::change_window_attributes(&self.connection, window.as_xcb_window(), &values);
+    }
+
+    fn is_viewable(&self, window: &Window) -> bool {
+        match xcb::get_window_attributes(&self.connection, window.as_xcb_window()).get_reply() {
+            Ok(attributes) => attributes.map_state() == xcb::MAP_STATE_VIEWABLE as u8,
+            Err(_) => false,
+        }
+    }
+
+    fn focus_window(&self, window: Window) {
+        // The window may be gone by the time this arrives, the error that
+        // causes is not worth stopping for
+        xcb::set_input_focus(
             &self.connection,
             xcb::INPUT_FOCUS_POINTER_ROOT as u8,
             window.as_xcb_window(),
             xcb::CURRENT_TIME,
-        )
-        .request_check()
-        .expect("Could not set input focus to focus window");
-
-        ewmh::set_active_window_checked(&self.connection, self.root_index, window.as_xcb_window())
-            .request_check()
-            .expect("Could not set ewmh focus");
+        );
+        ewmh::set_active_window(&self.connection, self.root_index, window.as_xcb_window());
     }
 
-    pub fn delete_window(&self, window: &Window) {
+    fn delete_window(&self, window: &Window) {
         xcb::grab_server(&self.connection);
         if self
             .get_wm_protocols(window)
@@ -345,36 +1289,185 @@ impl Connection {
                 &event,
             );
         } else {
-            xcb::destroy_window(&self.connection, window.as_xcb_window());
+            xcb::kill_client(&self.connection, window.as_xcb_window());
         }
         xcb::ungrab_server(&self.connection);
     }
 
-    fn get_wm_protocols(&self, window: &Window) -> Vec<xcb::Atom> {
-        let protocols = icccm::get_wm_protocols(
+    fn grab_keys(&self, window: &Window, keys: &KeyMap) {
+        let key_symbols = keysyms::KeySymbols::new(&self.connection);
+        for key in keys.key_map.keys() {
+            // Keys missing from the keyboard are left unbound. Every window
+            // grabs the same keys, so only the root's grab warns about it
+            let keycode = match key_symbols.get_keycode(key.key).next() {
+                Some(k) => k,
+                None => {
+                    if window.as_xcb_window() == self.root_window.as_xcb_window() {
+                        eprintln!(
+                            "worm: no key on this keyboard for \"{}\", not binding it",
+                            key
+                        );
+                    }
+                    continue;
+                }
+            };
+            for locks in LOCK_COMBINATIONS.iter() {
+                xcb::grab_key(
+                    &self.connection,
+                    false,
+                    window.as_xcb_window(),
+                    (key.modifier | locks) as u16,
+                    keycode,
+                    xcb::GRAB_MODE_ASYNC as u8,
+                    xcb::GRAB_MODE_ASYNC as u8,
+                );
+            }
+        }
+    }
+
+    fn ungrab_keys(&self, window: &Window) {
+        xcb::ungrab_key(
             &self.connection,
+            xcb::GRAB_ANY as u8,
             window.as_xcb_window(),
-            self.atoms.WM_PROTOCOLS,
-        )
-        .get_reply()
-        .expect("Could not get wm protocols");
-        Vec::from(protocols.atoms())
+            xcb::MOD_MASK_ANY as u16,
+        );
     }
 
-    /// function to find xcb::idow geometry as (x, y, width, height)
-    pub fn get_window_geometry(&self, window: xcb::Window) -> (u32, u32, u32, u32) {
-        println!("GETTING GEO FOR WINDOW: {}", window);
-        let geo = xcb::get_geometry(&self.connection, window)
+    fn track_window_events(&self, window: &Window) {
+        let values = [(
+            xcb::CW_EVENT_MASK,
+            xcb::EVENT_MASK_ENTER_WINDOW
+                | xcb::EVENT_MASK_STRUCTURE_NOTIFY
+                | xcb::EVENT_MASK_PROPERTY_CHANGE,
+        )];
+
+        xcb::change_window_attributes_checked(&self.connection, window.as_xcb_window(), &values)
+            .request_check()
+            .expect("Could not track window events");
+    }
+
+    fn stop_window_events(&self, window: &Window) {
+        let values = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)];
+        xcb::change_window_attributes_checked(&self.connection, window.as_xcb_window(), &values)
+            .request_check()
+            .expect("Could not stop window events");
+    }
+
+    fn get_wm_class(&self, window: &Window) -> (String, String) {
+        match icccm::get_wm_class(&self.connection, window.as_xcb_window()).get_reply() {
+            Ok(class) => (class.instance().to_string(), class.class().to_string()),
+            Err(_) => (String::new(), String::new()),
+        }
+    }
+
+    fn get_window_title(&self, window: &Window) -> String {
+        if let Ok(name) = ewmh::get_wm_name(&self.connection, window.as_xcb_window()).get_reply() {
+            return name.string().to_string();
+        }
+        match icccm::get_wm_name(&self.connection, window.as_xcb_window()).get_reply() {
+            Ok(name) => name.name().to_string(),
+            Err(_) => String::new(),
+        }
+    }
+
+    fn get_size_hints(&self, window: &Window) -> SizeHints {
+        let hints = match icccm::get_wm_normal_hints(&self.connection, window.as_xcb_window())
             .get_reply()
-            .expect(&format!(
-                "Could not get window geometry for window: {}",
-                window
-            ));
-        (
-            geo.x() as u32,
-            geo.y() as u32,
-            geo.width() as u32,
-            geo.height() as u32,
+        {
+            Ok(h) => h,
+            Err(_) => return SizeHints::default(),
+        };
+
+        let positive = |(w, h): (i32, i32)| {
+            if w > 0 || h > 0 {
+                Some((w.max(0) as u32, h.max(0) as u32))
+            } else {
+                None
+            }
+        };
+        let aspect = hints.aspect().and_then(|((min_x, min_y), (max_x, max_y))| {
+            if min_y > 0 && max_y > 0 {
+                Some((min_x as f32 / min_y as f32, max_x as f32 / max_y as f32))
+            } else {
+                None
+            }
+        });
+
+        SizeHints {
+            min: hints.min_size().and_then(positive),
+            max: hints.max_size().and_then(positive),
+            base: hints.base().and_then(positive),
+            increment: hints.resize().and_then(positive),
+            aspect,
+        }
+    }
+
+    fn is_urgent(&self, window: &Window) -> bool {
+        match icccm::get_wm_hints(&self.connection, window.as_xcb_window()).get_reply() {
+            Ok(hints) => hints.is_urgent().unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    fn is_fullscreen(&self, window: &Window) -> bool {
+        match ewmh::get_wm_state(&self.connection, window.as_xcb_window()).get_reply() {
+            Ok(state) => state
+                .atoms()
+                .contains(&self.connection.WM_STATE_FULLSCREEN()),
+            Err(_) => false,
+        }
+    }
+
+    fn get_transient_for(&self, window: &Window) -> Option<WindowId> {
+        let reply = xcb::get_property(
+            &self.connection,
+            false,
+            window.as_xcb_window(),
+            xcb::ATOM_WM_TRANSIENT_FOR,
+            xcb::ATOM_WINDOW,
+            0,
+            1,
         )
+        .get_reply()
+        .ok()?;
+        reply
+            .value::<xcb::Window>()
+            .first()
+            .copied()
+            .filter(|&w| w != xcb::NONE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_hints_constrain_sizes() {
+        let free = SizeHints::default();
+        assert_eq!(free.constrain(300, 200), (300, 200));
+        assert_eq!(free.constrain(0, 0), (1, 1));
+
+        let terminal = SizeHints {
+            base: Some((4, 2)),
+            increment: Some((8, 16)),
+            ..Default::default()
+        };
+        assert_eq!(terminal.constrain(300, 200), (300, 194));
+
+        let bounded = SizeHints {
+            min: Some((100, 100)),
+            max: Some((400, 300)),
+            ..Default::default()
+        };
+        assert_eq!(bounded.constrain(50, 500), (100, 300));
+
+        let square = SizeHints {
+            aspect: Some((1.0, 1.0)),
+            ..Default::default()
+        };
+        assert_eq!(square.constrain(300, 200), (200, 200));
+        assert_eq!(square.constrain(200, 300), (200, 200));
     }
 }